@@ -0,0 +1,348 @@
+//! just enough json for the binaries, so the crate stays dependency free
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// in the order written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// the member of an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(v) if *v >= 0.0 && v.fract() == 0.0 && *v <= u64::MAX as f64 => {
+                Some(*v as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    pub fn parse(text: &str) -> Result<Json, (usize, &'static str)> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        if parser.pos != parser.text.len() {
+            return Err((parser.pos, "trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub fn write(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+            Json::Number(v) if v.is_finite() => {
+                let _ = write!(out, "{}", v);
+            }
+            Json::Number(_) => out.push_str("null"),
+            Json::Str(v) => write_str(v, out),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            Json::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    write_str(key, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out);
+        f.write_str(&out)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(v: &str) -> Self {
+        Json::Str(v.to_string())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(v: usize) -> Self {
+        Json::Number(v as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(v: bool) -> Self {
+        Json::Bool(v)
+    }
+}
+
+fn write_str(v: &str, out: &mut String) {
+    out.push('"');
+    for ch in v.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+/// deeper input is an error rather than a stack overflow
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while matches!(self.text.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, (usize, &'static str)> {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err((self.pos, "expected a value"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, (usize, &'static str)> {
+        if depth > MAX_DEPTH {
+            return Err((self.pos, "nested too deeply"));
+        }
+        self.whitespace();
+        match self.text.get(self.pos) {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err((self.pos, "expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    if self.text.get(self.pos) != Some(&b'"') {
+                        return Err((self.pos, "expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    if self.text.get(self.pos) != Some(&b':') {
+                        return Err((self.pos, "expected :"));
+                    }
+                    self.pos += 1;
+                    members.push((key, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err((self.pos, "expected , or }")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err((self.pos, "expected a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, (usize, &'static str)> {
+        let begin = self.pos;
+        while matches!(
+            self.text.get(self.pos),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[begin..self.pos])
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Json::Number)
+            .ok_or((begin, "invalid number"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, (usize, &'static str)> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| u32::from_str_radix(v, 16).ok())
+            .ok_or((self.pos, "invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, (usize, &'static str)> {
+        let begin = self.pos;
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let ch = *self.text.get(self.pos).ok_or((begin, "unclosed string"))?;
+            self.pos += 1;
+            match ch {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self.text.get(self.pos).ok_or((begin, "unclosed string"))?;
+                    self.pos += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err((self.pos - 2, "invalid escape")),
+                    };
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+                }
+                ch => out.push(ch),
+            }
+        }
+        // the input was a str, and escapes are written as utf-8
+        String::from_utf8(out).map_err(|_| (begin, "invalid string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"a":[1,-2.5,true,null],"b":{"c":"x\"\né😀"},"d":[]}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-2.5),
+                Json::Bool(true),
+                Json::Null,
+            ]))
+        );
+        assert_eq!(
+            json.get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str),
+            Some("x\"\né😀")
+        );
+        assert_eq!(
+            json.to_string(),
+            r#"{"a":[1,-2.5,true,null],"b":{"c":"x\"\né😀"},"d":[]}"#
+        );
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let json = Json::parse(r#"["\u00e9\ud83d\ude00\/"]"#).unwrap();
+        assert_eq!(json, Json::Array(vec![Json::from("é😀/")]));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Json::parse("[1,]"), Err((3, "expected a value")));
+        assert_eq!(Json::parse("{\"a\" 1}"), Err((5, "expected :")));
+        assert_eq!(Json::parse("\"abc"), Err((0, "unclosed string")));
+        assert_eq!(Json::parse("1 2"), Err((2, "trailing characters")));
+        assert!(Json::parse(&"[".repeat(1000)).is_err());
+    }
+}
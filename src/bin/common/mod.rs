@@ -0,0 +1,227 @@
+//! shared by the binaries. each binary uses some of this
+#![allow(dead_code)]
+
+pub mod json;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Read;
+
+use language_expression::eval::Value;
+use language_expression::Token;
+
+use json::Json;
+
+/// the contents of a file, or stdin for `-`
+pub fn read_input(path: &str) -> std::io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut input = Vec::new();
+        std::io::stdin().read_to_end(&mut input)?;
+        Ok(input)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+/// the zero based line, and the zero based column in chars, of an offset
+pub fn line_col(input: &[u8], offset: usize) -> (usize, usize) {
+    let offset = offset.min(input.len());
+    let line_begin = input[..offset]
+        .iter()
+        .rposition(|c| *c == b'\n')
+        .map_or(0, |i| i + 1);
+    let line = input[..line_begin].iter().filter(|c| **c == b'\n').count();
+    let col = String::from_utf8_lossy(&input[line_begin..offset])
+        .chars()
+        .count();
+    (line, col)
+}
+
+/// a message pointing at an offset in the input, like
+///
+/// ```txt
+/// path:1:1
+/// {hi,ab
+/// ^ error: unclosed function
+/// ```
+pub fn render(path: &str, input: &[u8], offset: usize, label: &str, message: &str) -> String {
+    let offset = offset.min(input.len());
+    let (line, col) = line_col(input, offset);
+    let line_begin = offset
+        - input[..offset]
+            .iter()
+            .rev()
+            .take_while(|c| **c != b'\n')
+            .count();
+    let line_end = input[offset..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map_or(input.len(), |i| offset + i);
+    let text = String::from_utf8_lossy(&input[line_begin..line_end]);
+    let text = text.trim_end_matches('\r');
+    // keep tabs, so the caret lines up
+    let pad: String = text
+        .chars()
+        .take(col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{}:{}:{}\n{}\n{}^ {}: {}\n",
+        path,
+        line + 1,
+        col + 1,
+        text,
+        pad,
+        label,
+        message
+    )
+}
+
+/// the tokens as a table, like
+///
+/// ```txt
+/// 0  FUNCTION
+///        name: "f"
+///        number args: 1
+///        delta: 3
+///        first arg delta: 2
+/// 1  |   CHARACTER 'a'
+/// 2  END_ARG (delta: None)
+/// ```
+pub fn dump_tokens(tokens: &[Token]) -> String {
+    let width = tokens.len().saturating_sub(1).to_string().len() + 2;
+    let mut out = String::new();
+    // the index one past each function the current token is in
+    let mut ends: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        while ends.last().is_some_and(|end| *end <= i) {
+            ends.pop();
+        }
+        let mut depth = ends.len();
+        // END_ARG lines up with its function
+        if let Token::FunctionArgEnd(_) = token {
+            depth -= 1;
+        }
+        let indent = "|   ".repeat(depth);
+        let _ = write!(out, "{:<width$}{}", i, indent, width = width);
+        match token {
+            Token::Invalid => out.push_str("INVALID\n"),
+            Token::Character(character) => {
+                let _ = writeln!(out, "CHARACTER '{}'", character.val.escape_ascii());
+            }
+            Token::FunctionArgEnd(arg_end) => {
+                let _ = match arg_end.arg_delta {
+                    Some(delta) => writeln!(out, "END_ARG (delta: {})", delta),
+                    None => writeln!(out, "END_ARG (delta: None)"),
+                };
+            }
+            Token::Function(function) => {
+                out.push_str("FUNCTION\n");
+                let detail = format!("{:width$}{}    ", "", indent, width = width);
+                let _ = writeln!(out, "{}name: \"{}\"", detail, function.name.escape_ascii());
+                let _ = writeln!(out, "{}number args: {}", detail, function.num_args);
+                let _ = writeln!(out, "{}delta: {}", detail, function.delta);
+                let _ = match function.first_arg_delta {
+                    Some(delta) => writeln!(out, "{}first arg delta: {}", detail, delta),
+                    None => writeln!(out, "{}first arg delta: None", detail),
+                };
+                if function.first_arg_delta.is_some() {
+                    ends.push(i + function.delta);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// json as a value for evaluation. numbers which are whole are integers
+pub fn json_value(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::default(),
+        Json::Bool(v) => Value::Bool(*v),
+        Json::Number(v) if v.fract() == 0.0 && v.abs() < (1u64 << 53) as f64 => {
+            Value::Int(*v as i64)
+        }
+        Json::Number(v) => Value::Float(*v),
+        Json::Str(v) => Value::Str(v.as_bytes().to_vec()),
+        Json::Array(items) => Value::List(items.iter().map(json_value).collect()),
+        Json::Object(_) => Value::default(),
+    }
+}
+
+/// adds the members of a json object as variables. nested objects give dotted
+/// names, so `{"user":{"name":"x"}}` gives `user.name`
+pub fn add_json_vars(json: &Json, prefix: &str, vars: &mut BTreeMap<Vec<u8>, Value>) {
+    match json {
+        Json::Object(members) => {
+            for (key, value) in members {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                add_json_vars(value, &name, vars);
+            }
+        }
+        _ => {
+            vars.insert(prefix.as_bytes().to_vec(), json_value(json));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use language_expression::tokenize_to_vec;
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            render("x", b"{hi,ab", 0, "error", "unclosed function"),
+            "x:1:1\n{hi,ab\n^ error: unclosed function\n"
+        );
+        assert_eq!(
+            render("x", b"a\n\t\xc3\xa9{b\r\nc", 5, "error", "oops"),
+            "x:2:3\n\t\u{e9}{b\n\t ^ error: oops\n"
+        );
+        assert_eq!(line_col(b"ab\n", 3), (1, 0));
+    }
+
+    #[test]
+    fn dumping() {
+        let tokens = tokenize_to_vec(b"{outer,{inner,a},1}z").unwrap();
+        let expected = "\
+0  FUNCTION
+       name: \"outer\"
+       number args: 2
+       delta: 7
+       first arg delta: 4
+1  |   FUNCTION
+   |       name: \"inner\"
+   |       number args: 1
+   |       delta: 3
+   |       first arg delta: 2
+2  |   |   CHARACTER 'a'
+3  |   END_ARG (delta: None)
+4  END_ARG (delta: 2)
+5  |   CHARACTER '1'
+6  END_ARG (delta: None)
+7  CHARACTER 'z'
+";
+        assert_eq!(dump_tokens(&tokens), expected);
+    }
+
+    #[test]
+    fn vars() {
+        let json = Json::parse(r#"{"n":2,"x":1.5,"user":{"name":"Bo","tags":["a"]}}"#).unwrap();
+        let mut vars = BTreeMap::new();
+        add_json_vars(&json, "", &mut vars);
+        assert_eq!(vars[&b"n"[..]], Value::Int(2));
+        assert_eq!(vars[&b"x"[..]], Value::Float(1.5));
+        assert_eq!(vars[&b"user.name"[..]], Value::Str(b"Bo".to_vec()));
+        assert_eq!(
+            vars[&b"user.tags"[..]],
+            Value::List(vec![Value::Str(b"a".to_vec())])
+        );
+    }
+}
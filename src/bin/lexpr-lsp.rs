@@ -0,0 +1,621 @@
+//! a language server for expressions, over stdio. it gives diagnostics, hover
+//! for function signatures, completion of function names, the matching brace
+//! as the definition of a brace, and formatting
+
+#[path = "common/mod.rs"]
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use language_expression::fix::format;
+use language_expression::lint::{lint, Severity};
+use language_expression::schema::{check, ArgKind, Schema, Signature};
+use language_expression::{recover, tokenize_to_vec, unclosed, Token};
+
+use common::json::Json;
+
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+const SEVERITY_HINT: usize = 4;
+
+const COMPLETION_FUNCTION: usize = 3;
+
+struct Server {
+    schema: Schema<'static>,
+    /// the text of each open document, by uri
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+    /// set by the exit notification
+    exit_code: Option<i32>,
+}
+
+impl Server {
+    fn new() -> Self {
+        Self {
+            schema: Schema::standard(),
+            documents: BTreeMap::new(),
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// handles a message from the client, giving the messages to send back
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            // a notification
+            None => return self.notification(method, params),
+        };
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/hover" => Some(self.document(params, hover).unwrap_or(Json::Null)),
+            "textDocument/completion" => {
+                Some(self.document(params, completion).unwrap_or(Json::Null))
+            }
+            "textDocument/definition" => {
+                Some(self.document(params, matching_brace).unwrap_or(Json::Null))
+            }
+            "textDocument/formatting" => {
+                Some(self.document(params, formatting).unwrap_or(Json::Null))
+            }
+            _ => None,
+        };
+        let response = match result {
+            Some(result) => {
+                Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)])
+            }
+            None => error_response(id, METHOD_NOT_FOUND, "method not found"),
+        };
+        vec![response]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .to_string();
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|d| d.get("text"));
+                let text = text.and_then(Json::as_str).unwrap_or("").to_string();
+                let diagnostics = self.publish(&uri, &text);
+                self.documents.insert(uri, text);
+                vec![diagnostics]
+            }
+            "textDocument/didChange" => {
+                // the whole text is sent on each change
+                let text = match params.get("contentChanges") {
+                    Some(Json::Array(changes)) => changes.last().and_then(|c| c.get("text")),
+                    _ => None,
+                };
+                let text = match text.and_then(Json::as_str) {
+                    Some(v) => v.to_string(),
+                    None => return Vec::new(),
+                };
+                let diagnostics = self.publish(&uri, &text);
+                self.documents.insert(uri, text);
+                vec![diagnostics]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![self.publish(&uri, "")]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// calls f with the schema, the document from the params, and the offset of
+    /// the position from the params
+    fn document(
+        &self,
+        params: &Json,
+        f: fn(&Schema, &str, &str, usize) -> Option<Json>,
+    ) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let text = self.documents.get(uri)?;
+        let at = match params.get("position") {
+            Some(position) => offset(text, position)?,
+            None => 0,
+        };
+        f(&self.schema, uri, text, at)
+    }
+
+    fn publish(&self, uri: &str, text: &str) -> Json {
+        Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object([
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(diagnostics(&self.schema, text))),
+                ]),
+            ),
+        ])
+    }
+}
+
+fn error_response(id: Json, code: f64, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", Json::Number(code)), ("message", message.into())]),
+        ),
+    ])
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // full text on each change
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", true.into()),
+                (
+                    "completionProvider",
+                    Json::object([("triggerCharacters", Json::Array(vec!["{".into()]))]),
+                ),
+                ("definitionProvider", true.into()),
+                ("documentFormattingProvider", true.into()),
+            ]),
+        ),
+        ("serverInfo", Json::object([("name", "lexpr-lsp".into())])),
+    ])
+}
+
+/// the lsp position of an offset. characters are counted in utf-16
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset];
+    let line_begin = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[line_begin..].chars().map(char::len_utf16).sum();
+    Json::object([("line", line.into()), ("character", character.into())])
+}
+
+/// the offset of an lsp position, clamped to the end of its line
+fn offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+    let mut line_begin = 0;
+    for _ in 0..line {
+        line_begin += text[line_begin..].find('\n')? + 1;
+    }
+    let mut units = 0;
+    for (i, ch) in text[line_begin..].char_indices() {
+        if units >= character || ch == '\n' {
+            return Some(line_begin + i);
+        }
+        units += ch.len_utf16();
+    }
+    Some(text.len())
+}
+
+fn range(text: &str, begin: usize, end: usize) -> Json {
+    let begin = begin.min(text.len());
+    let mut end = end.clamp(begin, text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    Json::object([
+        ("start", position(text, begin)),
+        ("end", position(text, end)),
+    ])
+}
+
+fn diagnostic(
+    text: &str,
+    offset: usize,
+    severity: usize,
+    message: &str,
+    code: Option<&str>,
+) -> Json {
+    let mut members = vec![
+        ("range".to_string(), range(text, offset, offset + 1)),
+        ("severity".to_string(), severity.into()),
+        ("source".to_string(), "lexpr".into()),
+        ("message".to_string(), message.into()),
+    ];
+    if let Some(code) = code {
+        members.push(("code".to_string(), code.into()));
+    }
+    Json::Object(members)
+}
+
+fn diagnostics(schema: &Schema, text: &str) -> Vec<Json> {
+    let tokens = match tokenize_to_vec(text.as_bytes()) {
+        Ok(v) => v,
+        Err((offset, reason)) => {
+            return vec![diagnostic(text, offset, SEVERITY_ERROR, reason, None)]
+        }
+    };
+    let mut found = Vec::new();
+    for violation in check(&tokens, schema) {
+        found.push(diagnostic(
+            text,
+            violation.offset,
+            SEVERITY_ERROR,
+            violation.reason,
+            None,
+        ));
+    }
+    for lint in lint(text.as_bytes(), &tokens) {
+        let severity = match lint.severity {
+            Severity::Warning => SEVERITY_WARNING,
+            Severity::Hint => SEVERITY_HINT,
+        };
+        found.push(diagnostic(
+            text,
+            lint.offset,
+            severity,
+            lint.message,
+            Some(lint.id),
+        ));
+    }
+    found
+}
+
+/// like `{round,number[,number]}` or `{add,number,...}`
+fn signature_label(signature: &Signature) -> String {
+    let mut label = format!("{{{}", String::from_utf8_lossy(signature.name));
+    let shown = signature.max_args.unwrap_or(signature.min_args.max(1));
+    for n in 0..shown {
+        let kind = match signature.arg_kind(n) {
+            ArgKind::Any => "any",
+            ArgKind::Text => "text",
+            ArgKind::Number => "number",
+        };
+        if n < signature.min_args {
+            label.push(',');
+            label.push_str(kind);
+        } else {
+            label.push_str(&format!("[,{}", kind));
+        }
+    }
+    label.push_str(&"]".repeat(shown.saturating_sub(signature.min_args)));
+    if signature.max_args.is_none() {
+        label.push_str(",...");
+    }
+    label.push('}');
+    label
+}
+
+fn hover(schema: &Schema, _uri: &str, text: &str, at: usize) -> Option<Json> {
+    // work while typing, when functions aren't closed yet
+    let recovered = recover(text.as_bytes());
+    let tokens = tokenize_to_vec(&recovered).ok()?;
+    let function = tokens.iter().find_map(|token| match token {
+        Token::Function(f) if f.offset <= at && at <= f.offset + f.name.len() => Some(*f),
+        _ => None,
+    })?;
+    let name = String::from_utf8_lossy(function.name);
+    let contents = if let Some(variable) = name.strip_prefix('$') {
+        format!("variable `{}`", variable)
+    } else if let Some(signature) = schema.get(function.name) {
+        format!("```\n{}\n```", signature_label(signature))
+    } else {
+        "unknown function".to_string()
+    };
+    let end = function.offset + 1 + function.name.len();
+    Some(Json::object([
+        (
+            "contents",
+            Json::object([
+                ("kind", "markdown".into()),
+                ("value", contents.as_str().into()),
+            ]),
+        ),
+        ("range", range(text, function.offset, end)),
+    ]))
+}
+
+fn completion(schema: &Schema, _uri: &str, text: &str, at: usize) -> Option<Json> {
+    let name_begin = unclosed(&text.as_bytes()[..at]).name?;
+    let prefix = &text.as_bytes()[name_begin + 1..at];
+    let items = schema
+        .signatures()
+        .filter(|s| s.name.starts_with(prefix))
+        .map(|s| {
+            let label = String::from_utf8_lossy(s.name);
+            Json::object([
+                ("label", label.as_ref().into()),
+                ("kind", COMPLETION_FUNCTION.into()),
+                ("detail", signature_label(s).as_str().into()),
+            ])
+        })
+        .collect();
+    Some(Json::Array(items))
+}
+
+/// from a function's `{` to its `}`, and back
+fn matching_brace(_schema: &Schema, uri: &str, text: &str, at: usize) -> Option<Json> {
+    let tokens = tokenize_to_vec(text.as_bytes()).ok()?;
+    for (i, token) in tokens.iter().enumerate() {
+        let function = match token {
+            Token::Function(v) => v,
+            _ => continue,
+        };
+        let close = match function.first_arg_delta {
+            // the END_ARG of the last arg
+            Some(_) => tokens[i + function.delta - 1].offset(),
+            None => function.offset + 1 + function.name.len(),
+        };
+        let target = if at == function.offset {
+            close
+        } else if at == close {
+            function.offset
+        } else {
+            continue;
+        };
+        return Some(Json::object([
+            ("uri", uri.into()),
+            ("range", range(text, target, target + 1)),
+        ]));
+    }
+    None
+}
+
+fn formatting(_schema: &Schema, _uri: &str, text: &str, _at: usize) -> Option<Json> {
+    let formatted = String::from_utf8(format(text.as_bytes()).ok()?).ok()?;
+    if formatted == text {
+        return Some(Json::Array(Vec::new()));
+    }
+    Some(Json::Array(vec![Json::object([
+        ("range", range(text, 0, text.len())),
+        ("newText", formatted.as_str().into()),
+    ])]))
+}
+
+/// the body of the next message, or None at the end of input
+fn read_message(reader: &mut impl BufRead) -> std::io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(writer: &mut impl Write, message: &Json) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// serves until the exit notification or the end of input, giving the exit code
+fn run(mut reader: impl BufRead, mut writer: impl Write) -> std::io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut reader)? {
+        let replies = match std::str::from_utf8(&body).ok().map(Json::parse) {
+            Some(Ok(message)) => server.handle(&message),
+            _ => vec![error_response(Json::Null, PARSE_ERROR, "parse error")],
+        };
+        for reply in &replies {
+            write_message(&mut writer, reply)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+    Ok(1)
+}
+
+fn main() {
+    let stdin = std::io::stdin();
+    let code = run(stdin.lock(), std::io::stdout().lock()).unwrap_or_else(|e| {
+        eprintln!("lexpr-lsp: {}", e);
+        1
+    });
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// runs the server on some messages, giving its exit code and replies
+    fn session(messages: &[String]) -> (i32, Vec<Json>) {
+        let mut input = Vec::new();
+        for message in messages {
+            write!(
+                input,
+                "Content-Length: {}\r\n\r\n{}",
+                message.len(),
+                message
+            )
+            .unwrap();
+        }
+        let mut output = Vec::new();
+        let code = run(&input[..], &mut output).unwrap();
+        let mut reader = &output[..];
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            replies.push(Json::parse(std::str::from_utf8(&body).unwrap()).unwrap());
+        }
+        (code, replies)
+    }
+
+    fn request(id: usize, method: &str, params: &str) -> String {
+        format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#,
+            id, method, params
+        )
+    }
+
+    fn notify(method: &str, params: &str) -> String {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#,
+            method, params
+        )
+    }
+
+    fn open(text: &str) -> String {
+        let text = Json::from(text).to_string();
+        notify(
+            "textDocument/didOpen",
+            &format!(
+                r#"{{"textDocument":{{"uri":"file:///a","text":{}}}}}"#,
+                text
+            ),
+        )
+    }
+
+    fn at(method: &str, id: usize, line: usize, character: usize) -> String {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///a"}},"position":{{"line":{},"character":{}}}}}"#,
+            line, character
+        );
+        request(id, method, &params)
+    }
+
+    fn result(replies: &[Json], id: usize) -> &Json {
+        replies
+            .iter()
+            .find(|r| r.get("id").and_then(Json::as_u64) == Some(id as u64))
+            .and_then(|r| r.get("result"))
+            .unwrap()
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (code, replies) = session(&[
+            request(1, "initialize", "{}"),
+            notify("initialized", "{}"),
+            request(2, "nope", "{}"),
+            request(3, "shutdown", "null"),
+            notify("exit", "null"),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 3);
+        let capabilities = result(&replies, 1).get("capabilities").unwrap();
+        assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+        let error = replies[1].get("error").unwrap();
+        assert_eq!(error.get("code"), Some(&Json::Number(METHOD_NOT_FOUND)));
+        assert_eq!(result(&replies, 3), &Json::Null);
+
+        let (code, _) = session(&[notify("exit", "null")]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn publishing_diagnostics() {
+        let (_, replies) = session(&[open("é{if,a}\n\\,{plurl")]);
+        let params = replies[0].get("params").unwrap();
+        assert_eq!(params.get("uri").and_then(Json::as_str), Some("file:///a"));
+        let expected = r#"[{"range":{"start":{"line":1,"character":3},"end":{"line":1,"character":4}},"severity":1,"source":"lexpr","message":"function name wasn't completed"}]"#;
+        assert_eq!(params.get("diagnostics").unwrap().to_string(), expected);
+
+        let (_, replies) = session(&[open("é{if,a}\n\\,")]);
+        let diagnostics = replies[0].get("params").and_then(|p| p.get("diagnostics"));
+        let expected = r#"[{"range":{"start":{"line":0,"character":1},"end":{"line":0,"character":2}},"severity":1,"source":"lexpr","message":"too few arguments"},{"range":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}},"severity":4,"source":"lexpr","message":"escape isn't needed outside of a function","code":"redundant-escape"}]"#;
+        assert_eq!(diagnostics.unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn hovering() {
+        let (_, replies) = session(&[
+            open("{round,{$x}} {plural,"),
+            at("textDocument/hover", 1, 0, 3),
+            at("textDocument/hover", 2, 0, 8),
+            at("textDocument/hover", 3, 0, 16),
+            at("textDocument/hover", 4, 0, 6),
+        ]);
+        let contents = |id| {
+            let contents = result(&replies, id).get("contents");
+            contents.and_then(|c| c.get("value")).and_then(Json::as_str)
+        };
+        assert_eq!(contents(1), Some("```\n{round,number[,number]}\n```"));
+        assert_eq!(contents(2), Some("variable `x`"));
+        assert_eq!(contents(3), Some("```\n{plural,number,...}\n```"));
+        assert_eq!(result(&replies, 4), &Json::Null);
+    }
+
+    #[test]
+    fn completing() {
+        let (_, replies) = session(&[
+            open("a {pl"),
+            at("textDocument/completion", 1, 0, 5),
+            at("textDocument/completion", 2, 0, 1),
+        ]);
+        let labels = match result(&replies, 1) {
+            Json::Array(items) => items
+                .iter()
+                .map(|i| i.get("label").and_then(Json::as_str).unwrap())
+                .collect::<Vec<_>>(),
+            other => panic!("{}", other),
+        };
+        assert_eq!(labels, ["plural"]);
+        assert_eq!(result(&replies, 2), &Json::Null);
+    }
+
+    #[test]
+    fn braces() {
+        let (_, replies) = session(&[
+            open("{a,{b},c}"),
+            at("textDocument/definition", 1, 0, 0),
+            at("textDocument/definition", 2, 0, 8),
+            at("textDocument/definition", 3, 0, 5),
+            at("textDocument/definition", 4, 0, 1),
+        ]);
+        let character = |id| {
+            let range = result(&replies, id).get("range");
+            range
+                .and_then(|r| r.get("start"))
+                .and_then(|s| s.get("character"))
+                .and_then(Json::as_u64)
+        };
+        assert_eq!(character(1), Some(8));
+        assert_eq!(character(2), Some(0));
+        assert_eq!(character(3), Some(3));
+        assert_eq!(result(&replies, 4), &Json::Null);
+    }
+
+    #[test]
+    fn formatting_documents() {
+        let params = r#"{"textDocument":{"uri":"file:///a"}}"#;
+        let (_, replies) = session(&[
+            open("a\\,b\n{c,\\n}"),
+            request(1, "textDocument/formatting", params),
+        ]);
+        let expected = r#"[{"range":{"start":{"line":0,"character":0},"end":{"line":1,"character":6}},"newText":"a,b\n{c,\\\\n}"}]"#;
+        assert_eq!(result(&replies, 1).to_string(), expected);
+
+        let (_, replies) = session(&[open("{a,"), request(1, "textDocument/formatting", params)]);
+        assert_eq!(result(&replies, 1), &Json::Null);
+    }
+}
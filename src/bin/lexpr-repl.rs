@@ -0,0 +1,298 @@
+//! an interactive prompt for expressions. each expression shows its tokens,
+//! diagnostics, and output. see `:help`
+
+#[path = "common/mod.rs"]
+mod common;
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+use language_expression::eval::{Environment, Locale, Value};
+use language_expression::lint::{lint, Severity};
+use language_expression::schema::{check, Schema};
+use language_expression::{tokenize_to_vec, unclosed};
+
+use common::{dump_tokens, render};
+
+const HELP: &str = "\
+type an expression to see its tokens, diagnostics and output. input continues
+onto the next line while a function is unclosed. an empty line ends it early
+
+:vars                 list the variables
+:set <name>=<value>   set a variable
+:unset <name>         remove a variable
+:funcs                list the functions and their number of args
+:locale <tag>         set the locale, like en or pt-PT
+:tokens on|off        show the tokens of each expression
+:history              list the previous expressions
+!<n>                  run expression n from the history again. !! is the last
+:quit                 exit. so does end of input
+";
+
+/// what happened to a line of input
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// text to print
+    Output(String),
+    /// an expression continues onto the next line
+    More,
+    Quit,
+}
+
+struct Repl {
+    env: Environment,
+    schema: Schema<'static>,
+    vars: BTreeMap<Vec<u8>, Value>,
+    history: Vec<String>,
+    /// the lines of an expression which isn't finished
+    pending: Option<String>,
+    show_tokens: bool,
+}
+
+impl Repl {
+    fn new() -> Self {
+        Self {
+            env: Environment::standard(),
+            schema: Schema::standard(),
+            vars: BTreeMap::new(),
+            history: Vec::new(),
+            pending: None,
+            show_tokens: true,
+        }
+    }
+
+    fn input(&mut self, line: &str) -> Step {
+        let expression = match self.pending.take() {
+            Some(mut pending) => {
+                if line.is_empty() {
+                    return Step::Output(self.run(pending));
+                }
+                pending.push('\n');
+                pending.push_str(line);
+                pending
+            }
+            None => {
+                if line.starts_with(':') {
+                    return self.command(line);
+                }
+                if let Some(n) = line.strip_prefix('!') {
+                    let entry = match n {
+                        "!" => self.history.last(),
+                        n => n
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|n| self.history.get(n.wrapping_sub(1))),
+                    };
+                    return match entry {
+                        Some(entry) => Step::Output(self.run(entry.clone())),
+                        None => Step::Output("no such history entry\n".to_string()),
+                    };
+                }
+                line.to_string()
+            }
+        };
+        let state = unclosed(expression.as_bytes());
+        if state.name.is_some() || !state.functions.is_empty() {
+            self.pending = Some(expression);
+            return Step::More;
+        }
+        Step::Output(self.run(expression))
+    }
+
+    fn command(&mut self, line: &str) -> Step {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut out = String::new();
+        match command {
+            ":help" => out.push_str(HELP),
+            ":quit" | ":q" => return Step::Quit,
+            ":vars" => {
+                for (name, value) in &self.vars {
+                    let value = value.clone().into_bytes();
+                    let _ = writeln!(
+                        out,
+                        "{} = {}",
+                        String::from_utf8_lossy(name),
+                        String::from_utf8_lossy(&value)
+                    );
+                }
+            }
+            ":set" => match rest.split_once('=') {
+                Some((name, value)) => {
+                    let value = Value::Str(value.as_bytes().to_vec());
+                    self.vars.insert(name.trim().as_bytes().to_vec(), value);
+                }
+                None => out.push_str("usage: :set <name>=<value>\n"),
+            },
+            ":unset" => {
+                if self.vars.remove(rest.as_bytes()).is_none() {
+                    out.push_str("no such variable\n");
+                }
+            }
+            ":funcs" => {
+                for name in self.env.names() {
+                    let args = match self.schema.get(name) {
+                        Some(s) if Some(s.min_args) == s.max_args => s.min_args.to_string(),
+                        Some(s) => match s.max_args {
+                            Some(max) => format!("{} to {}", s.min_args, max),
+                            None => format!("{} or more", s.min_args),
+                        },
+                        None => "any".to_string(),
+                    };
+                    let _ = writeln!(out, "{:<10} {} args", String::from_utf8_lossy(name), args);
+                }
+            }
+            ":locale" => match Locale::from_tag(rest.as_bytes()) {
+                Some(locale) => self.env.set_locale(locale),
+                None => out.push_str("unknown locale\n"),
+            },
+            ":tokens" => match rest {
+                "on" => self.show_tokens = true,
+                "off" => self.show_tokens = false,
+                _ => out.push_str("usage: :tokens on|off\n"),
+            },
+            ":history" => {
+                for (i, entry) in self.history.iter().enumerate() {
+                    let _ = writeln!(out, "{:>3}  {}", i + 1, entry.replace('\n', "\n     "));
+                }
+            }
+            _ => out.push_str("unknown command. see :help\n"),
+        }
+        Step::Output(out)
+    }
+
+    /// the tokens, diagnostics and output of an expression
+    fn run(&mut self, expression: String) -> String {
+        let input = expression.as_bytes();
+        let mut out = String::new();
+        match tokenize_to_vec(input) {
+            Err((offset, reason)) => out.push_str(&render("input", input, offset, "error", reason)),
+            Ok(tokens) => {
+                if self.show_tokens {
+                    out.push_str(&dump_tokens(&tokens));
+                }
+                for violation in check(&tokens, &self.schema) {
+                    let reason = violation.reason;
+                    out.push_str(&render("input", input, violation.offset, "error", reason));
+                }
+                for lint in lint(input, &tokens) {
+                    let label = match lint.severity {
+                        Severity::Warning => "warning",
+                        Severity::Hint => "hint",
+                    };
+                    out.push_str(&render("input", input, lint.offset, label, lint.message));
+                }
+                match self.env.eval_with(&tokens, &self.vars) {
+                    Ok(output) => {
+                        let _ = writeln!(out, "= {}", String::from_utf8_lossy(&output));
+                    }
+                    Err((offset, reason)) => {
+                        out.push_str(&render("input", input, offset, "error", reason))
+                    }
+                }
+            }
+        }
+        if self.history.last() != Some(&expression) {
+            self.history.push(expression);
+        }
+        out
+    }
+}
+
+fn main() {
+    let mut repl = Repl::new();
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut line = String::new();
+    loop {
+        let prompt = if repl.pending.is_some() { ". " } else { "> " };
+        let _ = write!(stdout, "{}", prompt);
+        let _ = stdout.flush();
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                // finish an expression cut off by the end of input
+                if repl.pending.is_some() {
+                    if let Step::Output(out) = repl.input("") {
+                        let _ = write!(stdout, "\n{}", out);
+                    }
+                }
+                let _ = writeln!(stdout);
+                return;
+            }
+            Ok(_) => (),
+        }
+        let text = line.trim_end_matches(['\n', '\r']);
+        match repl.input(text) {
+            Step::Output(out) => {
+                let _ = write!(stdout, "{}", out);
+            }
+            Step::More => (),
+            Step::Quit => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(repl: &mut Repl, line: &str) -> String {
+        match repl.input(line) {
+            Step::Output(out) => out,
+            step => panic!("{:?}", step),
+        }
+    }
+
+    #[test]
+    fn evaluating() {
+        let mut repl = Repl::new();
+        repl.input(":tokens off");
+        assert_eq!(output(&mut repl, "{add,1,2}"), "= 3\n");
+        let out = output(&mut repl, "{if,x}");
+        assert!(out.contains("^ error: too few arguments"), "{}", out);
+        assert!(
+            out.ends_with("^ error: wrong number of arguments\n"),
+            "{}",
+            out
+        );
+        repl.input(":tokens on");
+        assert!(output(&mut repl, "a").starts_with("0  CHARACTER 'a'\n"));
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let mut repl = Repl::new();
+        repl.input(":tokens off");
+        assert_eq!(repl.input("{concat,a,"), Step::More);
+        assert_eq!(repl.input("{concat,b,"), Step::More);
+        assert_eq!(repl.input("c}}"), Step::Output("= a\nb\nc\n".to_string()));
+        assert_eq!(repl.input("{if,a"), Step::More);
+        let out = output(&mut repl, "");
+        assert!(out.ends_with("^ error: unclosed function\n"), "{}", out);
+        assert_eq!(repl.history, ["{concat,a,\n{concat,b,\nc}}", "{if,a"]);
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::new();
+        repl.input(":tokens off");
+        output(&mut repl, ":set name=Bo");
+        assert_eq!(output(&mut repl, ":vars"), "name = Bo\n");
+        assert_eq!(output(&mut repl, "hi {$name}"), "= hi Bo\n");
+        output(&mut repl, ":unset name");
+        assert_eq!(output(&mut repl, ":vars"), "");
+        assert!(output(&mut repl, ":funcs").contains("if         2 to 3 args\n"));
+        output(&mut repl, ":locale de");
+        assert_eq!(output(&mut repl, "{number,1234.5}"), "= 1.234,5\n");
+        assert_eq!(
+            output(&mut repl, ":history"),
+            "  1  hi {$name}\n  2  {number,1234.5}\n"
+        );
+        output(&mut repl, ":set name=Al");
+        assert_eq!(output(&mut repl, "!1"), "= hi Al\n");
+        assert_eq!(output(&mut repl, "!9"), "no such history entry\n");
+        assert_eq!(repl.input(":quit"), Step::Quit);
+    }
+}
@@ -0,0 +1,227 @@
+//! a command line tool for expressions. see `lexpr help`
+
+#[path = "common/mod.rs"]
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::ExitCode;
+
+use language_expression::eval::{Environment, Locale, Value};
+use language_expression::fix::format;
+use language_expression::lint::{lint, Severity};
+use language_expression::schema::{check, Schema};
+use language_expression::tokenize_to_vec;
+
+use common::json::Json;
+use common::{add_json_vars, dump_tokens, read_input, render};
+
+const USAGE: &str = "\
+usage: lexpr <command> [options] <file>...
+
+a file of - is stdin
+
+commands:
+  tokens <file>             print the tokens of a file
+  check <file>...           print errors, schema violations and lints
+  fmt [--check] <file>...   apply safe fixes to files in place, and print
+                            stdin with them. with --check, list the files
+                            which would change instead
+  eval [options] <file>     evaluate a file and print the output
+      --var <name>=<value>  a variable. can be given more than once
+      --vars <json file>    variables from the members of a json object.
+                            nested objects give dotted names
+      --locale <tag>        the locale, like en or pt-PT
+";
+
+/// a failure which was already reported
+struct Failed;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("tokens") => tokens(&args[1..]),
+        Some("check") => check_files(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("eval") => eval(&args[1..]),
+        Some("help" | "-h" | "--help") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(usage("expected a command")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failed) => ExitCode::FAILURE,
+    }
+}
+
+fn usage(message: &str) -> Failed {
+    eprint!("lexpr: {}\n\n{}", message, USAGE);
+    Failed
+}
+
+fn read(path: &str) -> Result<Vec<u8>, Failed> {
+    read_input(path).map_err(|e| {
+        eprintln!("lexpr: {}: {}", path, e);
+        Failed
+    })
+}
+
+fn write_stdout(bytes: &[u8]) -> Result<(), Failed> {
+    std::io::stdout().write_all(bytes).map_err(|e| {
+        eprintln!("lexpr: {}", e);
+        Failed
+    })
+}
+
+fn tokens(args: &[String]) -> Result<(), Failed> {
+    let [path] = args else {
+        return Err(usage("tokens takes one file"));
+    };
+    let input = read(path)?;
+    match tokenize_to_vec(&input) {
+        Ok(tokens) => write_stdout(dump_tokens(&tokens).as_bytes()),
+        Err((offset, reason)) => {
+            eprint!("{}", render(path, &input, offset, "error", reason));
+            Err(Failed)
+        }
+    }
+}
+
+fn check_files(paths: &[String]) -> Result<(), Failed> {
+    if paths.is_empty() {
+        return Err(usage("check takes at least one file"));
+    }
+    let schema = Schema::standard();
+    let mut failed = false;
+    for path in paths {
+        let input = read(path)?;
+        let tokens = match tokenize_to_vec(&input) {
+            Ok(v) => v,
+            Err((offset, reason)) => {
+                eprint!("{}", render(path, &input, offset, "error", reason));
+                failed = true;
+                continue;
+            }
+        };
+        // in order of offset, errors first
+        let mut found: Vec<(usize, &str, String)> = Vec::new();
+        for violation in check(&tokens, &schema) {
+            found.push((violation.offset, "error", violation.reason.to_string()));
+        }
+        for lint in lint(&input, &tokens) {
+            let label = match lint.severity {
+                Severity::Warning => "warning",
+                Severity::Hint => "hint",
+            };
+            found.push((
+                lint.offset,
+                label,
+                format!("{} [{}]", lint.message, lint.id),
+            ));
+        }
+        found.sort_by_key(|(offset, label, _)| (*offset, *label != "error"));
+        for (offset, label, message) in found {
+            failed |= label == "error";
+            eprint!("{}", render(path, &input, offset, label, &message));
+        }
+    }
+    if failed {
+        Err(Failed)
+    } else {
+        Ok(())
+    }
+}
+
+fn fmt(args: &[String]) -> Result<(), Failed> {
+    let check_only = args.first().is_some_and(|a| a == "--check");
+    let paths = if check_only { &args[1..] } else { args };
+    if paths.is_empty() {
+        return Err(usage("fmt takes at least one file"));
+    }
+    let mut failed = false;
+    for path in paths {
+        let input = read(path)?;
+        let formatted = match format(&input) {
+            Ok(v) => v,
+            Err((offset, reason)) => {
+                eprint!("{}", render(path, &input, offset, "error", reason));
+                failed = true;
+                continue;
+            }
+        };
+        // stdin is always written back, so it can be piped to a file
+        if path == "-" && !check_only {
+            write_stdout(&formatted)?;
+            continue;
+        }
+        if formatted == input {
+            continue;
+        }
+        if check_only {
+            println!("{}", path);
+            failed = true;
+        } else if let Err(e) = std::fs::write(path, &formatted) {
+            eprintln!("lexpr: {}: {}", path, e);
+            failed = true;
+        }
+    }
+    if failed {
+        Err(Failed)
+    } else {
+        Ok(())
+    }
+}
+
+fn eval(args: &[String]) -> Result<(), Failed> {
+    let mut env = Environment::standard();
+    let mut vars: BTreeMap<Vec<u8>, Value> = BTreeMap::new();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--var" => {
+                let var = args.next().ok_or_else(|| usage("--var takes a value"))?;
+                let (name, value) = var
+                    .split_once('=')
+                    .ok_or_else(|| usage("--var takes <name>=<value>"))?;
+                vars.insert(
+                    name.as_bytes().to_vec(),
+                    Value::Str(value.as_bytes().to_vec()),
+                );
+            }
+            "--vars" => {
+                let file = args.next().ok_or_else(|| usage("--vars takes a file"))?;
+                let text = String::from_utf8_lossy(&read(file)?).into_owned();
+                let json = Json::parse(&text).map_err(|(offset, reason)| {
+                    eprint!("{}", render(file, text.as_bytes(), offset, "error", reason));
+                    Failed
+                })?;
+                if !matches!(json, Json::Object(_)) {
+                    eprintln!("lexpr: {}: expected a json object", file);
+                    return Err(Failed);
+                }
+                add_json_vars(&json, "", &mut vars);
+            }
+            "--locale" => {
+                let tag = args.next().ok_or_else(|| usage("--locale takes a tag"))?;
+                let locale =
+                    Locale::from_tag(tag.as_bytes()).ok_or_else(|| usage("unknown locale"))?;
+                env.set_locale(locale);
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(usage("eval takes one file")),
+        }
+    }
+    let path = path.ok_or_else(|| usage("eval takes one file"))?;
+    let input = read(path)?;
+    let result = tokenize_to_vec(&input).and_then(|tokens| env.eval_with(&tokens, &vars));
+    match result {
+        Ok(output) => write_stdout(&output),
+        Err((offset, reason)) => {
+            eprint!("{}", render(path, &input, offset, "error", reason));
+            Err(Failed)
+        }
+    }
+}
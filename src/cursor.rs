@@ -0,0 +1,254 @@
+//! moves around the token array in any direction. deltas only point forward,
+//! so going up or back either scans from the start, or uses `Parents`
+
+use alloc::vec::Vec;
+
+use crate::{function_args, Token};
+
+/// the function around each token, from one pass over the tokens. an END_ARG's
+/// parent is the function it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parents(Vec<Option<usize>>);
+
+impl Parents {
+    pub fn new(tokens: &[Token]) -> Self {
+        let mut parents = Vec::with_capacity(tokens.len());
+        // the index of each open function, and one past its end
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            while open.last().is_some_and(|(_, end)| *end <= i) {
+                open.pop();
+            }
+            parents.push(open.last().map(|(index, _)| *index));
+            if let Token::Function(function) = token {
+                if function.delta > 1 {
+                    open.push((i, i + function.delta));
+                }
+            }
+        }
+        Self(parents)
+    }
+
+    /// the parent of tokens\[index\], or None at the top level
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.0[index]
+    }
+}
+
+/// the parent of tokens\[index\], by skipping the functions which don't
+/// contain it
+fn scan_parent(tokens: &[Token], index: usize) -> Option<usize> {
+    let mut parent = None;
+    let mut i = 0;
+    while i < index {
+        match &tokens[i] {
+            Token::Function(function) if i + function.delta > index => {
+                parent = Some(i);
+                i += 1;
+            }
+            Token::Function(function) => i += function.delta,
+            _ => i += 1,
+        }
+    }
+    parent
+}
+
+/// a character or function in the tokens. moving gives None if there's
+/// nothing there
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    parents: Option<&'t Parents>,
+    index: usize,
+}
+
+impl<'t, 'a> Cursor<'t, 'a> {
+    /// going up or back scans the tokens from the start
+    pub fn new(tokens: &'t [Token<'a>], index: usize) -> Self {
+        Self {
+            tokens,
+            parents: None,
+            index,
+        }
+    }
+
+    /// going up or back follows the parents, which must be from these tokens
+    pub fn with_parents(tokens: &'t [Token<'a>], parents: &'t Parents, index: usize) -> Self {
+        debug_assert_eq!(tokens.len(), parents.0.len());
+        Self {
+            tokens,
+            parents: Some(parents),
+            index,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn token(&self) -> &'t Token<'a> {
+        &self.tokens[self.index]
+    }
+
+    fn at(&self, index: usize) -> Self {
+        Self { index, ..*self }
+    }
+
+    fn parent_of(&self, index: usize) -> Option<usize> {
+        match self.parents {
+            Some(parents) => parents.get(index),
+            None => scan_parent(self.tokens, index),
+        }
+    }
+
+    /// the function whose argument this is in
+    pub fn parent(&self) -> Option<Self> {
+        self.parent_of(self.index).map(|index| self.at(index))
+    }
+
+    pub fn next_sibling(&self) -> Option<Self> {
+        let next = match self.token() {
+            Token::Function(function) => self.index + function.delta,
+            _ => self.index + 1,
+        };
+        match self.tokens.get(next)? {
+            Token::Character(_) | Token::Function(_) => Some(self.at(next)),
+            _ => None,
+        }
+    }
+
+    pub fn prev_sibling(&self) -> Option<Self> {
+        let parent = self.parent_of(self.index);
+        let prev = self.index.checked_sub(1)?;
+        if Some(prev) == parent {
+            return None;
+        }
+        if self.parents.is_some() {
+            // an END_ARG of the parent ends the argument before this one
+            if matches!(self.tokens[prev], Token::FunctionArgEnd(_))
+                && self.parent_of(prev) == parent
+            {
+                return None;
+            }
+            // otherwise, go up from the token before to this one's level
+            let mut index = prev;
+            while self.parent_of(index) != parent {
+                index = self.parent_of(index)?;
+            }
+            return Some(self.at(index));
+        }
+
+        let begin = match parent {
+            Some(parent) => {
+                function_args(self.tokens, parent)
+                    .find(|range| range.contains(&self.index))?
+                    .start
+            }
+            None => 0,
+        };
+        let mut sibling = self.at(begin);
+        loop {
+            let next = sibling.next_sibling()?;
+            if next.index == self.index {
+                return Some(sibling);
+            }
+            sibling = next;
+        }
+    }
+
+    /// the first character or function in the first argument of this function
+    pub fn first_child_arg(&self) -> Option<Self> {
+        match self.token() {
+            Token::Function(function) if function.first_arg_delta.is_some() => {
+                match self.tokens[self.index + 1] {
+                    Token::Character(_) | Token::Function(_) => Some(self.at(self.index + 1)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// the number of functions around this. 0 at the top level
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut index = self.index;
+        while let Some(parent) = self.parent_of(index) {
+            depth += 1;
+            index = parent;
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    // 0  CHARACTER 'a'
+    // 1  FUNCTION f
+    // 2  |   CHARACTER 'b'
+    // 3  |   FUNCTION g
+    // 4  |   |   CHARACTER 'c'
+    // 5  |   |   END_ARG
+    // 6  |   FUNCTION h
+    // 7  |   END_ARG
+    // 8  |   END_ARG
+    // 9  |   CHARACTER 'd'
+    // 10 |   END_ARG
+    // 11 CHARACTER 'e'
+    const INPUT: &[u8] = b"a{f,b{g,c}{h},,d}e";
+
+    fn moves(cursor: Cursor) -> [Option<usize>; 4] {
+        [
+            cursor.parent(),
+            cursor.next_sibling(),
+            cursor.prev_sibling(),
+            cursor.first_child_arg(),
+        ]
+        .map(|c| c.map(|c| c.index()))
+    }
+
+    #[test]
+    fn navigation() {
+        let tokens = tokenize_to_vec(INPUT).unwrap();
+        let parents = Parents::new(&tokens);
+        let expected = [
+            (0, [None, Some(1), None, None], 0),
+            (1, [None, Some(11), Some(0), Some(2)], 0),
+            (2, [Some(1), Some(3), None, None], 1),
+            (3, [Some(1), Some(6), Some(2), Some(4)], 1),
+            (4, [Some(3), None, None, None], 2),
+            (6, [Some(1), None, Some(3), None], 1),
+            (9, [Some(1), None, None, None], 1),
+            (11, [None, None, Some(1), None], 0),
+        ];
+        for (index, want, depth) in expected {
+            for cursor in [
+                Cursor::new(&tokens, index),
+                Cursor::with_parents(&tokens, &parents, index),
+            ] {
+                assert_eq!(moves(cursor), want, "{}", index);
+                assert_eq!(cursor.depth(), depth);
+            }
+        }
+    }
+
+    #[test]
+    fn parents_match_scanning() {
+        for input in [INPUT, b"{a,{b,{c,{d}}},x,{e,y}}{f}z", b""] {
+            let tokens = tokenize_to_vec(input).unwrap();
+            let parents = Parents::new(&tokens);
+            for index in 0..tokens.len() {
+                assert_eq!(parents.get(index), scan_parent(&tokens, index));
+                if let Token::FunctionArgEnd(_) = tokens[index] {
+                    continue;
+                }
+                let plain = Cursor::new(&tokens, index);
+                let table = Cursor::with_parents(&tokens, &parents, index);
+                assert_eq!(moves(plain), moves(table), "{}", index);
+            }
+        }
+    }
+}
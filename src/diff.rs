@@ -0,0 +1,521 @@
+//! compares the trees of two inputs, so a change deep in an expression shows
+//! as that change, not as changed lines
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::incremental::close_offset;
+use crate::rewrite::{arg_span, same_tokens};
+use crate::{function_args, Token};
+
+/// sequences longer than this, multiplied, are compared as one replacement
+/// after their common start and end
+const MAX_TABLE: usize = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// a function in the same place, with a different name. the ranges are
+    /// the names
+    FunctionRenamed,
+    /// the old range is empty, where the argument would be
+    ArgInserted,
+    /// the new range is empty, where the argument would be
+    ArgRemoved,
+    /// characters or functions replaced. either range can be empty, for text
+    /// which is only inserted or removed
+    TextChanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: Kind,
+    /// bytes of the old input
+    pub old: Range<usize>,
+    /// bytes of the new input
+    pub new: Range<usize>,
+}
+
+/// the modulus of the hashes, a prime so that few ranges collide
+const MODULUS: u64 = (1 << 61) - 1;
+const BASE: u64 = 0x1f3d_5b79_a2c4_e687 % MODULUS;
+
+fn mul(a: u64, b: u64) -> u64 {
+    (a as u128 * b as u128 % MODULUS as u128) as u64
+}
+
+/// a number for a token, apart from its offset
+fn token_hash(token: &Token) -> u64 {
+    // fnv-1a
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut add = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+        }
+    };
+    match token {
+        Token::Character(character) => add(&[0, character.val]),
+        Token::Function(function) => {
+            add(&[1]);
+            add(&function.num_args.to_le_bytes());
+            add(&function.delta.to_le_bytes());
+            add(&function.first_arg_delta.map_or(0, |v| v + 1).to_le_bytes());
+            add(function.name);
+        }
+        Token::FunctionArgEnd(end) => {
+            add(&[2]);
+            add(&end.arg_delta.map_or(0, |v| v + 1).to_le_bytes());
+        }
+        Token::Invalid => add(&[3]),
+    }
+    hash % MODULUS
+}
+
+/// hashes of each start of some tokens, so a range can be hashed without
+/// walking it
+struct Hashes {
+    prefix: Vec<u64>,
+    powers: Vec<u64>,
+}
+
+impl Hashes {
+    fn new(tokens: &[Token]) -> Self {
+        let mut prefix = vec![0];
+        let mut powers = vec![1];
+        for token in tokens {
+            let last = prefix[prefix.len() - 1];
+            prefix.push((mul(last, BASE) + token_hash(token)) % MODULUS);
+            powers.push(mul(powers[powers.len() - 1], BASE));
+        }
+        Hashes { prefix, powers }
+    }
+
+    /// the same for ranges with the same tokens, apart from offsets
+    fn range(&self, range: &Range<usize>) -> u64 {
+        let before = mul(self.prefix[range.start], self.powers[range.len()]);
+        (self.prefix[range.end] + MODULUS - before) % MODULUS
+    }
+}
+
+/// an input and its tokens
+#[derive(Clone, Copy)]
+struct Side<'t, 'a> {
+    input: &'a [u8],
+    tokens: &'t [Token<'a>],
+    hashes: &'t Hashes,
+}
+
+impl Side<'_, '_> {
+    /// the bytes of the character or function at tokens\[index\]
+    fn span(&self, index: usize) -> Range<usize> {
+        match &self.tokens[index] {
+            Token::Function(function) => {
+                function.offset..close_offset(self.tokens, index, function) + 1
+            }
+            token => {
+                let offset = token.offset();
+                let escaped = self.input[offset] == b'\\'
+                    && matches!(self.input.get(offset + 1), Some(b'{' | b'}' | b',' | b'\\'));
+                offset..offset + 1 + escaped as usize
+            }
+        }
+    }
+
+    /// the indices of the characters and functions in a range of tokens
+    fn nodes(&self, range: Range<usize>) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            nodes.push(i);
+            i += match &self.tokens[i] {
+                Token::Function(function) => function.delta,
+                _ => 1,
+            };
+        }
+        nodes
+    }
+}
+
+/// the pairs of a longest common subsequence of two lists, by index
+fn lcs(n: usize, m: usize, eq: impl Fn(usize, usize) -> bool) -> Vec<(usize, usize)> {
+    let mut start = 0;
+    while start < n && start < m && eq(start, start) {
+        start += 1;
+    }
+    let mut end = 0;
+    while end < n - start && end < m - start && eq(n - 1 - end, m - 1 - end) {
+        end += 1;
+    }
+    let mut pairs: Vec<(usize, usize)> = (0..start).map(|i| (i, i)).collect();
+
+    let (a, b) = (n - start - end, m - start - end);
+    if a > 0 && b > 0 && a.saturating_mul(b) <= MAX_TABLE {
+        // lengths of the common subsequences of the suffixes
+        let mut table = vec![0u32; (a + 1) * (b + 1)];
+        let at = |i: usize, j: usize| i * (b + 1) + j;
+        for i in (0..a).rev() {
+            for j in (0..b).rev() {
+                table[at(i, j)] = if eq(start + i, start + j) {
+                    table[at(i + 1, j + 1)] + 1
+                } else {
+                    table[at(i + 1, j)].max(table[at(i, j + 1)])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a && j < b {
+            if eq(start + i, start + j) {
+                pairs.push((start + i, start + j));
+                i += 1;
+                j += 1;
+            } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    pairs.extend((0..end).rev().map(|k| (n - 1 - k, m - 1 - k)));
+    pairs
+}
+
+/// a step of the diff, kept on a stack so any nesting can be compared
+enum Step {
+    Change(Change),
+    /// two sequences, and the offsets just past each
+    Sequence(Range<usize>, usize, Range<usize>, usize),
+    /// the arguments of two functions
+    Args(usize, usize),
+}
+
+struct Differ<'t, 'a> {
+    old: Side<'t, 'a>,
+    new: Side<'t, 'a>,
+}
+
+impl<'t, 'a> Differ<'t, 'a> {
+    /// compares two sequences of characters and functions. the ends are the
+    /// offsets just past each sequence
+    fn sequence(&self, a: Range<usize>, a_end: usize, b: Range<usize>, b_end: usize) -> Vec<Step> {
+        let (old, new) = (self.old, self.new);
+        let (a, b) = (old.nodes(a), new.nodes(b));
+        // functions line up by name, to be compared inside
+        let mut pairs = lcs(a.len(), b.len(), |i, j| {
+            match (&old.tokens[a[i]], &new.tokens[b[j]]) {
+                (Token::Character(x), Token::Character(y)) => x.val == y.val,
+                (Token::Function(x), Token::Function(y)) => x.name == y.name,
+                _ => false,
+            }
+        });
+        pairs.push((a.len(), b.len()));
+
+        let mut steps = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in pairs {
+            let (gap_a, gap_b) = (&a[i..next_i], &b[j..next_j]);
+            let functions = |side: Side, gap: &[usize]| match gap {
+                [index] => matches!(side.tokens[*index], Token::Function(_)),
+                _ => false,
+            };
+            if functions(old, gap_a) && functions(new, gap_b) {
+                let (x, y) = (gap_a[0], gap_b[0]);
+                let name = |side: Side, index: usize| {
+                    let offset = side.tokens[index].offset() + 1;
+                    match &side.tokens[index] {
+                        Token::Function(function) => offset..offset + function.name.len(),
+                        _ => unreachable!(),
+                    }
+                };
+                steps.push(Step::Change(Change {
+                    kind: Kind::FunctionRenamed,
+                    old: name(old, x),
+                    new: name(new, y),
+                }));
+                steps.push(Step::Args(x, y));
+            } else if !gap_a.is_empty() || !gap_b.is_empty() {
+                let bytes = |side: Side, gap: &[usize], next: Option<&usize>, end: usize| match gap
+                {
+                    [] => {
+                        let at = next.map_or(end, |index| side.span(*index).start);
+                        at..at
+                    }
+                    _ => side.span(gap[0]).start..side.span(gap[gap.len() - 1]).end,
+                };
+                steps.push(Step::Change(Change {
+                    kind: Kind::TextChanged,
+                    old: bytes(old, gap_a, a.get(next_i), a_end),
+                    new: bytes(new, gap_b, b.get(next_j), b_end),
+                }));
+            }
+            if next_i < a.len() && matches!(old.tokens[a[next_i]], Token::Function(_)) {
+                steps.push(Step::Args(a[next_i], b[next_j]));
+            }
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+        steps
+    }
+
+    /// a number for each argument, the same for arguments with the same
+    /// tokens. each is hashed once, and only compared in full with arguments
+    /// of the same hash
+    fn classes(&self, a: &[Range<usize>], b: &[Range<usize>]) -> (Vec<usize>, Vec<usize>) {
+        let mut seen: BTreeMap<u64, Vec<(&'t [Token<'a>], usize)>> = BTreeMap::new();
+        let mut count = 0;
+        let mut class = |side: Side<'t, 'a>, arg: &Range<usize>| {
+            let tokens = &side.tokens[arg.clone()];
+            let found = seen.entry(side.hashes.range(arg)).or_default();
+            match found.iter().find(|(other, _)| same_tokens(other, tokens)) {
+                Some(&(_, id)) => id,
+                None => {
+                    found.push((tokens, count));
+                    count += 1;
+                    count - 1
+                }
+            }
+        };
+        let a = a.iter().map(|arg| class(self.old, arg)).collect();
+        let b = b.iter().map(|arg| class(self.new, arg)).collect();
+        (a, b)
+    }
+
+    /// compares the arguments of two functions
+    fn args(&self, x: usize, y: usize) -> Vec<Step> {
+        let (old, new) = (self.old, self.new);
+        let a: Vec<_> = function_args(old.tokens, x).collect();
+        let b: Vec<_> = function_args(new.tokens, y).collect();
+        let (class_a, class_b) = self.classes(&a, &b);
+        let mut pairs = lcs(a.len(), b.len(), |i, j| class_a[i] == class_b[j]);
+        pairs.push((a.len(), b.len()));
+
+        // where an argument would go, before the next one or at the `}`
+        let place =
+            |side: Side, index: usize, args: &[Range<usize>], next: usize| match args.get(next) {
+                Some(arg) => arg_span(side.tokens, index, arg).start,
+                None => match &side.tokens[index] {
+                    Token::Function(function) => close_offset(side.tokens, index, function),
+                    _ => unreachable!(),
+                },
+            };
+
+        let mut steps = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in pairs {
+            // arguments changed in place are compared inside
+            while i < next_i && j < next_j {
+                let a_end = arg_span(old.tokens, x, &a[i]).end;
+                let b_end = arg_span(new.tokens, y, &b[j]).end;
+                steps.push(Step::Sequence(a[i].clone(), a_end, b[j].clone(), b_end));
+                i += 1;
+                j += 1;
+            }
+            while i < next_i {
+                let at = place(new, y, &b, j);
+                steps.push(Step::Change(Change {
+                    kind: Kind::ArgRemoved,
+                    old: arg_span(old.tokens, x, &a[i]),
+                    new: at..at,
+                }));
+                i += 1;
+            }
+            while j < next_j {
+                let at = place(old, x, &a, i);
+                steps.push(Step::Change(Change {
+                    kind: Kind::ArgInserted,
+                    old: at..at,
+                    new: arg_span(new.tokens, y, &b[j]),
+                }));
+                j += 1;
+            }
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+        steps
+    }
+}
+
+/// the changes from the old input to the new one. tokens must be from a
+/// successful `tokenize` of each input
+pub fn diff(
+    old_input: &[u8],
+    old_tokens: &[Token],
+    new_input: &[u8],
+    new_tokens: &[Token],
+) -> Vec<Change> {
+    let (old_hashes, new_hashes) = (Hashes::new(old_tokens), Hashes::new(new_tokens));
+    let differ = Differ {
+        old: Side {
+            input: old_input,
+            tokens: old_tokens,
+            hashes: &old_hashes,
+        },
+        new: Side {
+            input: new_input,
+            tokens: new_tokens,
+            hashes: &new_hashes,
+        },
+    };
+    let mut changes = Vec::new();
+    let mut steps = vec![Step::Sequence(
+        0..old_tokens.len(),
+        old_input.len(),
+        0..new_tokens.len(),
+        new_input.len(),
+    )];
+    // the steps of each comparison are pushed last first, so the changes
+    // come out in order
+    while let Some(step) = steps.pop() {
+        let more = match step {
+            Step::Change(change) => {
+                changes.push(change);
+                continue;
+            }
+            Step::Sequence(a, a_end, b, b_end) => differ.sequence(a, a_end, b, b_end),
+            Step::Args(x, y) => differ.args(x, y),
+        };
+        steps.extend(more.into_iter().rev());
+    }
+    changes
+}
+
+/// a line for each change, like `4..5 -> 4..6  changed "a" to "bc"`
+pub fn render(old_input: &[u8], new_input: &[u8], changes: &[Change]) -> String {
+    let mut out = String::new();
+    for change in changes {
+        let old = String::from_utf8_lossy(&old_input[change.old.clone()]);
+        let new = String::from_utf8_lossy(&new_input[change.new.clone()]);
+        let _ = write!(
+            out,
+            "{}..{} -> {}..{}  ",
+            change.old.start, change.old.end, change.new.start, change.new.end
+        );
+        let _ = match change.kind {
+            Kind::FunctionRenamed => writeln!(out, "renamed {:?} to {:?}", old, new),
+            Kind::ArgInserted => writeln!(out, "inserted argument {:?}", new),
+            Kind::ArgRemoved => writeln!(out, "removed argument {:?}", old),
+            Kind::TextChanged if old.is_empty() => writeln!(out, "inserted {:?}", new),
+            Kind::TextChanged if new.is_empty() => writeln!(out, "removed {:?}", old),
+            Kind::TextChanged => writeln!(out, "changed {:?} to {:?}", old, new),
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn changes(old: &[u8], new: &[u8]) -> Vec<Change> {
+        let (a, b) = (tokenize_to_vec(old).unwrap(), tokenize_to_vec(new).unwrap());
+        diff(old, &a, new, &b)
+    }
+
+    fn rendered(old: &str, new: &str) -> String {
+        render(
+            old.as_bytes(),
+            new.as_bytes(),
+            &changes(old.as_bytes(), new.as_bytes()),
+        )
+    }
+
+    fn change(kind: Kind, old: Range<usize>, new: Range<usize>) -> Change {
+        Change { kind, old, new }
+    }
+
+    #[test]
+    fn same_inputs() {
+        assert_eq!(changes(b"a{f,b,{g}}c", b"a{f,b,{g}}c"), []);
+        assert_eq!(changes(b"", b""), []);
+        // only the spelling of an escape changed
+        assert_eq!(changes(b"\\\\n", b"\\n"), []);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            changes(b"x{bold,a,b}", b"x{strong,a,b}"),
+            [change(Kind::FunctionRenamed, 2..6, 2..8)]
+        );
+        // a rename, and a change inside it
+        assert_eq!(
+            rendered("{f,{g,ab}}", "{f,{h,ac}}"),
+            "4..5 -> 4..5  renamed \"g\" to \"h\"\n7..8 -> 7..8  changed \"b\" to \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            changes(b"{f,a,b,c}", b"{f,a,c}"),
+            [change(Kind::ArgRemoved, 5..6, 5..5)]
+        );
+        assert_eq!(
+            changes(b"{f,a}", b"{f,a,{g}}"),
+            [change(Kind::ArgInserted, 4..4, 5..8)]
+        );
+        assert_eq!(
+            changes(b"{f}", b"{f,x}"),
+            [change(Kind::ArgInserted, 2..2, 3..4)]
+        );
+        assert_eq!(
+            rendered("{if,a,yes,no}", "{if,b,yes,maybe,no}"),
+            "4..5 -> 4..5  changed \"a\" to \"b\"\n10..10 -> 10..15  inserted argument \"maybe\"\n"
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            rendered("ab{f}cd", "ab{f}xcd"),
+            "5..5 -> 5..6  inserted \"x\"\n"
+        );
+        assert_eq!(
+            rendered("a\\,b{g}", "ab"),
+            "1..3 -> 1..1  removed \"\\\\,\"\n4..7 -> 2..2  removed \"{g}\"\n"
+        );
+        assert_eq!(
+            rendered("{f,x{g}y}", "{f,x{h}{i}y}"),
+            "4..7 -> 4..10  changed \"{g}\" to \"{h}{i}\"\n"
+        );
+    }
+
+    #[test]
+    fn deep() {
+        let nested = |inner: &[u8]| {
+            let mut input = b"{f,".repeat(200000);
+            input.extend_from_slice(inner);
+            input.extend(b"}".repeat(200000));
+            input
+        };
+        let old = nested(b"a");
+        let mut new = old.clone();
+        new.push(b'x');
+        assert_eq!(
+            changes(&old, &new),
+            [change(
+                Kind::TextChanged,
+                old.len()..old.len(),
+                old.len()..new.len()
+            )]
+        );
+        // changed at the bottom
+        assert_eq!(
+            changes(&old, &nested(b"b")),
+            [change(Kind::TextChanged, 600000..600001, 600000..600001)]
+        );
+    }
+
+    #[test]
+    fn repeated_arguments() {
+        assert_eq!(
+            changes(b"{f,{g,a},{g,a},b}", b"{f,b,{g,a},{g,a},{g,a}}"),
+            [
+                change(Kind::ArgInserted, 3..3, 3..4),
+                change(Kind::TextChanged, 15..16, 17..22),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! changes to an input, expressed as byte ranges of the original
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// replaces a range of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: Vec<u8>,
+}
+
+impl Edit {
+    pub fn replace(range: Range<usize>, replacement: &[u8]) -> Self {
+        Self {
+            range,
+            replacement: replacement.to_vec(),
+        }
+    }
+
+    pub fn insert(offset: usize, text: &[u8]) -> Self {
+        Self::replace(offset..offset, text)
+    }
+
+    pub fn delete(range: Range<usize>) -> Self {
+        Self::replace(range, &[])
+    }
+}
+
+/// applies edits which don't overlap. edits which insert at the same offset
+/// are applied in the order given. on err, gives the start of an overlapping
+/// edit, or the end of an edit which is past the input
+pub fn apply(input: &[u8], edits: &[Edit]) -> Result<Vec<u8>, (usize, &'static str)> {
+    let mut order: Vec<&Edit> = edits.iter().collect();
+    order.sort_by_key(|edit| edit.range.start);
+    let mut output = Vec::with_capacity(input.len());
+    let mut copied = 0;
+    for edit in order {
+        if edit.range.start < copied {
+            return Err((edit.range.start, "overlapping edits"));
+        }
+        if edit.range.end > input.len() || edit.range.start > edit.range.end {
+            return Err((edit.range.end, "edit out of range"));
+        }
+        output.extend_from_slice(&input[copied..edit.range.start]);
+        output.extend_from_slice(&edit.replacement);
+        copied = edit.range.end;
+    }
+    output.extend_from_slice(&input[copied..]);
+    Ok(output)
+}
+
+/// an edit which resolves a problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// what the edit does
+    pub message: &'static str,
+    pub edit: Edit,
+    /// true if the tokens are the same after the edit, apart from offsets.
+    /// otherwise the meaning might change, and the fix is only a suggestion
+    pub safe: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applying() {
+        let edits = [
+            Edit::insert(5, b"}"),
+            Edit::replace(1..3, b"add"),
+            Edit::insert(5, b")"),
+            Edit::insert(3, b"_"),
+        ];
+        assert_eq!(apply(b"{ad,1", &edits), Ok(b"{add_,1})".to_vec()));
+        assert_eq!(apply(b"abc", &[]), Ok(b"abc".to_vec()));
+        let edits = [Edit::delete(0..2), Edit::insert(1, b"x")];
+        assert_eq!(apply(b"abc", &edits), Err((1, "overlapping edits")));
+        assert_eq!(
+            apply(b"abc", &[Edit::delete(2..4)]),
+            Err((4, "edit out of range"))
+        );
+    }
+}
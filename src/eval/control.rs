@@ -0,0 +1,122 @@
+//! conditionals and comparisons. only the arguments which decide the result
+//! are evaluated
+
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"if", if_);
+    env.register(b"eq", eq);
+    env.register(b"not", not);
+    env.register(b"and", and);
+    env.register(b"or", or);
+    env.register(b"switch", switch);
+}
+
+/// `{if,cond,then}` or `{if,cond,then,else}`
+fn if_(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 3)?;
+    if ev.arg_truthy(call, 0)? {
+        ev.arg(call, 1)
+    } else if call.num_args == 3 {
+        ev.arg(call, 2)
+    } else {
+        Ok(Value::default())
+    }
+}
+
+/// `{eq,a,b}`
+fn eq(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let lhs = ev.arg(call, 0)?;
+    let rhs = ev.arg(call, 1)?;
+    Ok(Value::Bool(lhs.equals(&rhs)))
+}
+
+/// `{not,x}`
+fn not(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 1)?;
+    Ok(Value::Bool(!ev.arg_truthy(call, 0)?))
+}
+
+/// `{and,...}`. stops at the first false arg. true if there are no args
+fn and(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    for i in 0..call.num_args {
+        if !ev.arg_truthy(call, i)? {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// `{or,...}`. stops at the first true arg. false if there are no args
+fn or(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    for i in 0..call.num_args {
+        if ev.arg_truthy(call, i)? {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// `{switch,value,case,out,case,out,...,default}`. the default is optional.
+/// cases are compared in order, and only the matching out is evaluated
+fn switch(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let value = ev.arg(call, 0)?;
+    let mut i = 1;
+    while i + 1 < call.num_args {
+        if ev.arg(call, i)?.equals(&value) {
+            return ev.arg(call, i + 1);
+        }
+        i += 2;
+    }
+    if i < call.num_args {
+        return ev.arg(call, i);
+    }
+    Ok(Value::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{Call, Environment, EvalError, Evaluation, Value};
+    use crate::tokenize_to_vec;
+    use alloc::vec::Vec;
+
+    fn fail(_: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+        Err((call.offset, "evaluated"))
+    }
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let mut env = Environment::standard();
+        env.register(b"fail", fail);
+        env.eval(&tokens)
+    }
+
+    #[test]
+    fn if_branches() {
+        assert_eq!(eval(b"{if,x,yes,no}"), Ok(b"yes".to_vec()));
+        assert_eq!(eval(b"{if,,yes,no}"), Ok(b"no".to_vec()));
+        assert_eq!(eval(b"{if,false,yes}"), Ok(b"".to_vec()));
+        assert_eq!(eval(b"{if,{eq,a,a},{fail},ok}"), Err((13, "evaluated")));
+        assert_eq!(eval(b"{if,{eq,a,b},{fail},ok}"), Ok(b"ok".to_vec()));
+        assert_eq!(eval(b"{if,x}"), Err((0, "wrong number of arguments")));
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval(b"{not,}{not,a}"), Ok(b"truefalse".to_vec()));
+        assert_eq!(eval(b"{and}{or}"), Ok(b"truefalse".to_vec()));
+        assert_eq!(eval(b"{and,a,,{fail}}"), Ok(b"false".to_vec()));
+        assert_eq!(eval(b"{or,,a,{fail}}"), Ok(b"true".to_vec()));
+        assert_eq!(eval(b"{and,a,{fail}}"), Err((7, "evaluated")));
+    }
+
+    #[test]
+    fn switch() {
+        let input = b"{switch,b,a,{fail},b,2,{fail}}";
+        assert_eq!(eval(input), Ok(b"2".to_vec()));
+        assert_eq!(eval(b"{switch,z,a,1,other}"), Ok(b"other".to_vec()));
+        assert_eq!(eval(b"{switch,z,a,1}"), Ok(b"".to_vec()));
+    }
+}
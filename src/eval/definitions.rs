@@ -0,0 +1,107 @@
+//! functions defined within the expression. `{def,name,body}` stores the body
+//! unevaluated, and each call evaluates it with the call's args available
+//! through `{arg,n}`. a function defined in the body of another only lasts
+//! until that call returns
+
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"def", def);
+    env.register(b"arg", arg);
+}
+
+/// `{def,name,body}`. the function can be called after this is evaluated
+fn def(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let name = ev.arg_bytes(call, 0)?;
+    if name.first() == Some(&b'$') || ev.env.get(&name).is_some() || ev.definition(&name).is_some()
+    {
+        return Err((call.offset, "function already defined"));
+    }
+    let body = ev.arg_range(call, 1);
+    ev.definitions.push((name, body));
+    Ok(Value::default())
+}
+
+/// `{arg,n}`. arg n of the innermost call to a defined function
+fn arg(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 1)?;
+    let index = ev.arg_index(call, 0)?;
+    let frame = match ev.frames.last() {
+        Some(v) => v,
+        None => return Err((call.offset, "arg used outside of a defined function")),
+    };
+    match frame.get(index) {
+        Some(v) => Ok(v.clone()),
+        None => Err((call.offset, "arg index out of range")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::DEPTH_LIMIT_REACHED;
+    use crate::tokenize_to_vec;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        Environment::standard().eval(&tokens)
+    }
+
+    #[test]
+    fn define_and_call() {
+        let input = b"{def,greet,{concat,Hello ,{arg,0}}}{greet,Bob}, {greet,Al}";
+        assert_eq!(eval(input), Ok(b"Hello Bob, Hello Al".to_vec()));
+        assert_eq!(eval(b"{def,two,{arg,1}}{two,a,b}"), Ok(b"b".to_vec()));
+        assert_eq!(
+            eval(b"{def,two,{arg,1}}{two,a}"),
+            Err((9, "arg index out of range"))
+        );
+        assert_eq!(
+            eval(b"{arg,0}"),
+            Err((0, "arg used outside of a defined function"))
+        );
+        assert_eq!(eval(b"{def,f,{arg,x}}{f}"), Err((12, "expected an index")));
+    }
+
+    #[test]
+    fn errors_at_call() {
+        assert_eq!(
+            eval(b"{greet,Bob}{def,greet,hi}"),
+            Err((0, "unknown function"))
+        );
+        assert_eq!(
+            eval(b"{def,f,a}{def,f,b}"),
+            Err((9, "function already defined"))
+        );
+        assert_eq!(eval(b"{def,if,a}"), Err((0, "function already defined")));
+        assert_eq!(
+            eval(b"{def,f,a}{def,g,{def,f,b}}{g}"),
+            Err((16, "function already defined"))
+        );
+        // the body isn't evaluated until called
+        assert_eq!(eval(b"{def,f,{nope}}ok"), Ok(b"ok".to_vec()));
+        assert_eq!(eval(b"{def,f,{nope}}{f}"), Err((7, "unknown function")));
+    }
+
+    #[test]
+    fn scoped_to_call() {
+        assert_eq!(eval(b"{def,f,{def,g,x}}{f}{f}"), Ok(b"".to_vec()));
+        let input = b"{def,f,{def,g,{arg,0}}{g,x}}{f}{f}";
+        assert_eq!(eval(input), Ok(b"xx".to_vec()));
+        assert_eq!(
+            eval(b"{def,f,{def,g,x}}{f}{g}"),
+            Err((20, "unknown function"))
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let input = b"{def,pad,{if,{eq,{arg,0},aaa},{arg,0},{pad,{arg,0}a}}}{pad,}";
+        assert_eq!(eval(input), Ok(b"aaa".to_vec()));
+
+        let tokens = tokenize_to_vec(b"{def,f,{f}}{f}").unwrap();
+        let env = Environment::standard();
+        assert_eq!(env.eval(&tokens), Err((7, DEPTH_LIMIT_REACHED)));
+    }
+}
@@ -0,0 +1,489 @@
+//! formatting numbers and dates. everything is computed here, so the output
+//! is the same on every platform
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::intl::Locale;
+use super::math::Number;
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"number", number);
+    env.register(b"date", date);
+}
+
+impl Locale {
+    /// the grouping and decimal separators. digits are always latin
+    fn separators(self) -> (&'static str, &'static str) {
+        match self {
+            Locale::Ar | Locale::En | Locale::Ja | Locale::Ko | Locale::Zh => (",", "."),
+            Locale::De | Locale::Es | Locale::It | Locale::Nl | Locale::Pt => (".", ","),
+            // narrow no-break space
+            Locale::Fr => ("\u{202f}", ","),
+            // no-break space
+            Locale::Cs | Locale::Pl | Locale::PtPt | Locale::Ru | Locale::Sv | Locale::Uk => {
+                ("\u{a0}", ",")
+            }
+        }
+    }
+}
+
+/// a pattern like `#,##0.00`, with optional text around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern<'p> {
+    prefix: &'p [u8],
+    suffix: &'p [u8],
+    /// digits in the last group, then in each group before it, if grouped. the
+    /// two differ for patterns like `#,##,##0`
+    grouping: Option<(usize, usize)>,
+    min_integer: usize,
+    min_fraction: usize,
+    max_fraction: usize,
+    /// a `%` in the prefix or suffix multiplies by 100
+    percent: bool,
+}
+
+impl<'p> Pattern<'p> {
+    fn parse(pattern: &'p [u8]) -> Option<Pattern<'p>> {
+        let is_number = |c: &u8| matches!(c, b'#' | b'0' | b',' | b'.');
+        let begin = pattern.iter().position(is_number)?;
+        let end = pattern.iter().rposition(is_number)? + 1;
+        let (prefix, body, suffix) = (&pattern[..begin], &pattern[begin..end], &pattern[end..]);
+        let (integer, fraction) = match body.iter().position(|c| *c == b'.') {
+            Some(i) => (&body[..i], &body[i + 1..]),
+            None => (body, &body[body.len()..]),
+        };
+        // zeros must come after #s in the integer, and before them in the fraction
+        if !integer.iter().all(is_number)
+            || integer
+                .iter()
+                .skip_while(|c| **c != b'0')
+                .any(|c| *c == b'#')
+            || !fraction.iter().all(|c| *c == b'0' || *c == b'#')
+            || fraction
+                .iter()
+                .skip_while(|c| **c != b'#')
+                .any(|c| *c == b'0')
+        {
+            return None;
+        }
+        let grouping = match integer.iter().rposition(|c| *c == b',') {
+            Some(i) if i + 1 < integer.len() => {
+                let primary = integer.len() - i - 1;
+                let secondary = match integer[..i].iter().rposition(|c| *c == b',') {
+                    Some(j) if j + 1 < i => i - j - 1,
+                    Some(_) => return None,
+                    None => primary,
+                };
+                Some((primary, secondary))
+            }
+            Some(_) => return None,
+            None => None,
+        };
+        Some(Pattern {
+            prefix,
+            suffix,
+            grouping,
+            // like ICU, there's always an integer digit
+            min_integer: integer.iter().filter(|c| **c == b'0').count().max(1),
+            min_fraction: fraction.iter().filter(|c| **c == b'0').count(),
+            max_fraction: fraction.len(),
+            percent: prefix.contains(&b'%') || suffix.contains(&b'%'),
+        })
+    }
+
+    /// `digits` is the integer and fraction digits of the value, without a sign
+    /// or leading zeros. `point` is how many of them are before the decimal point
+    fn write(
+        &self,
+        negative: bool,
+        mut digits: Vec<u8>,
+        mut point: usize,
+        locale: Locale,
+        out: &mut Vec<u8>,
+    ) {
+        if self.percent {
+            point += 2;
+        }
+        while digits.len() < point {
+            digits.push(b'0');
+        }
+        // round half away from zero at max_fraction, like `round`, on the digits
+        // so that it's exact
+        if digits.len() > point + self.max_fraction {
+            let cut = point + self.max_fraction;
+            let up = digits[cut] >= b'5';
+            digits.truncate(cut);
+            if up {
+                let mut i = cut;
+                loop {
+                    if i == 0 {
+                        digits.insert(0, b'1');
+                        point += 1;
+                        break;
+                    }
+                    i -= 1;
+                    if digits[i] == b'9' {
+                        digits[i] = b'0';
+                    } else {
+                        digits[i] += 1;
+                        break;
+                    }
+                }
+            }
+        }
+        let (integer, fraction) = digits.split_at(point);
+        let integer = match integer.iter().position(|c| *c != b'0') {
+            Some(i) => &integer[i..],
+            None => &[],
+        };
+        let mut fraction = fraction.to_vec();
+        while fraction.len() > self.min_fraction && fraction.last() == Some(&b'0') {
+            fraction.pop();
+        }
+        fraction.resize(fraction.len().max(self.min_fraction), b'0');
+        let zero = integer.is_empty() && fraction.iter().all(|c| *c == b'0');
+        let (group_separator, decimal_separator) = locale.separators();
+
+        if negative && !zero {
+            out.push(b'-');
+        }
+        out.extend_from_slice(self.prefix);
+        let padding = self.min_integer.saturating_sub(integer.len());
+        let len = padding + integer.len();
+        let integer = core::iter::repeat_n(&b'0', padding).chain(integer.iter());
+        for (i, c) in integer.enumerate() {
+            let remaining = len - i;
+            let separated = match self.grouping {
+                Some((primary, secondary)) => {
+                    remaining == primary
+                        || (remaining > primary && (remaining - primary).is_multiple_of(secondary))
+                }
+                None => false,
+            };
+            if i != 0 && separated {
+                out.extend_from_slice(group_separator.as_bytes());
+            }
+            out.push(*c);
+        }
+        if !fraction.is_empty() {
+            out.extend_from_slice(decimal_separator.as_bytes());
+            out.extend_from_slice(&fraction);
+        }
+        out.extend_from_slice(self.suffix);
+    }
+}
+
+/// `{number,value}` or `{number,value,pattern}`. the pattern is like ICU's:
+/// `0` is a digit, `#` is a digit only if needed, `,` groups and `.` is the
+/// decimal point. `%` multiplies by 100. other text is kept. the default is
+/// `#,##0.###`. rounds half away from zero
+fn number(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 2)?;
+    let value = ev.arg(call, 0)?;
+    let text = match value.as_number() {
+        // the shortest text for a float has all the digits, without an exponent
+        Some(Number::Float(v)) if v.is_finite() => v.to_string().into_bytes(),
+        Some(Number::Int(v)) => v.to_string().into_bytes(),
+        // nan and infinity, which can come from a context
+        _ => return Err((ev.arg_offset(call, 0), "expected a number")),
+    };
+    let pattern = if call.num_args == 2 {
+        ev.arg_bytes(call, 1)?
+    } else {
+        b"#,##0.###".to_vec()
+    };
+    let pattern = match Pattern::parse(&pattern) {
+        Some(v) => v,
+        None => return Err((ev.arg_offset(call, 1), "invalid number pattern")),
+    };
+    // the text is a valid number, so there's only a sign, digits and a point
+    let negative = text.first() == Some(&b'-');
+    let unsigned = text.strip_prefix(b"-").unwrap_or(&text);
+    let point = unsigned
+        .iter()
+        .position(|c| *c == b'.')
+        .unwrap_or(unsigned.len());
+    let digits = unsigned
+        .iter()
+        .copied()
+        .filter(u8::is_ascii_digit)
+        .collect();
+    let mut out = Vec::new();
+    pattern.write(negative, digits, point, ev.locale(), &mut out);
+    Ok(Value::Str(out))
+}
+
+/// a date and time in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DateTime {
+    year: i64,
+    /// 1 to 12
+    month: u32,
+    /// 1 to 31
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// 0 is sunday
+    weekday: u32,
+    /// 1 to 366
+    day_of_year: u32,
+}
+
+impl DateTime {
+    fn from_unix(seconds: i64) -> DateTime {
+        let days = seconds.div_euclid(86_400);
+        let time = seconds.rem_euclid(86_400) as u32;
+        // from Howard Hinnant's civil_from_days. eras are 400 years, starting
+        // on march 1st so that the leap day is last
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        const BEFORE_MONTH: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let day_of_year =
+            BEFORE_MONTH[month as usize - 1] + day + if leap && month > 2 { 1 } else { 0 };
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+            // the epoch was a thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+            day_of_year,
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// writes at least `width` digits, padded with zeros
+fn write_padded(out: &mut Vec<u8>, value: i64, width: usize) {
+    let digits = value.unsigned_abs().to_string();
+    if value < 0 {
+        out.push(b'-');
+    }
+    for _ in digits.len()..width {
+        out.push(b'0');
+    }
+    out.extend_from_slice(digits.as_bytes());
+}
+
+/// writes the date like strftime. gives the index of an unknown specifier
+fn write_date(format: &[u8], seconds: i64, out: &mut Vec<u8>) -> Result<(), usize> {
+    let t = DateTime::from_unix(seconds);
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            out.push(format[i]);
+            i += 1;
+            continue;
+        }
+        let specifier = match format.get(i + 1) {
+            Some(v) => *v,
+            None => return Err(i),
+        };
+        match specifier {
+            b'Y' => write_padded(out, t.year, 4),
+            b'y' => write_padded(out, t.year.rem_euclid(100), 2),
+            b'm' => write_padded(out, t.month as i64, 2),
+            b'd' => write_padded(out, t.day as i64, 2),
+            b'e' => {
+                if t.day < 10 {
+                    out.push(b' ');
+                }
+                write_padded(out, t.day as i64, 1);
+            }
+            b'H' => write_padded(out, t.hour as i64, 2),
+            b'I' => write_padded(out, ((t.hour + 11) % 12 + 1) as i64, 2),
+            b'M' => write_padded(out, t.minute as i64, 2),
+            b'S' => write_padded(out, t.second as i64, 2),
+            b'p' => out.extend_from_slice(if t.hour < 12 { b"AM" } else { b"PM" }),
+            b'j' => write_padded(out, t.day_of_year as i64, 3),
+            b'u' => write_padded(out, ((t.weekday + 6) % 7 + 1) as i64, 1),
+            b'w' => write_padded(out, t.weekday as i64, 1),
+            b'a' => out.extend_from_slice(&WEEKDAYS[t.weekday as usize].as_bytes()[..3]),
+            b'A' => out.extend_from_slice(WEEKDAYS[t.weekday as usize].as_bytes()),
+            b'b' => out.extend_from_slice(&MONTHS[t.month as usize - 1].as_bytes()[..3]),
+            b'B' => out.extend_from_slice(MONTHS[t.month as usize - 1].as_bytes()),
+            b's' => write_padded(out, seconds, 1),
+            b'F' => write_date(b"%Y-%m-%d", seconds, out)?,
+            b'T' => write_date(b"%H:%M:%S", seconds, out)?,
+            b'z' => out.extend_from_slice(b"+0000"),
+            b'Z' => out.extend_from_slice(b"UTC"),
+            b'%' => out.push(b'%'),
+            _ => return Err(i),
+        }
+        i += 2;
+    }
+    Ok(())
+}
+
+/// `{date,unix_seconds}` or `{date,unix_seconds,format}`. in UTC, with a format
+/// like strftime: `%Y %y %m %d %e %H %I %M %S %p %j %u %w %a %A %b %B %s %F %T
+/// %z %Z %%`. names are in english. the default is `%Y-%m-%dT%H:%M:%SZ`
+fn date(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 2)?;
+    let seconds = match ev.arg(call, 0)?.as_number() {
+        Some(Number::Int(v)) => v,
+        _ => return Err((ev.arg_offset(call, 0), "expected an integer")),
+    };
+    // keeps the year within an i64 and four digits or so, which is plenty
+    const LIMIT: i64 = 1 << 40;
+    if !(-LIMIT..=LIMIT).contains(&seconds) {
+        return Err((ev.arg_offset(call, 0), "date out of range"));
+    }
+    let format = if call.num_args == 2 {
+        ev.arg_bytes(call, 1)?
+    } else {
+        b"%Y-%m-%dT%H:%M:%SZ".to_vec()
+    };
+    let mut out = Vec::new();
+    if write_date(&format, seconds, &mut out).is_err() {
+        return Err((ev.arg_offset(call, 1), "unknown date format specifier"));
+    }
+    Ok(Value::Str(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+    use alloc::collections::BTreeMap;
+
+    fn eval(locale: &[u8], input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let mut env = Environment::standard();
+        env.set_locale(Locale::from_tag(locale).unwrap());
+        env.eval(&tokens)
+    }
+
+    fn number(locale: &[u8], value: &[u8], pattern: &[u8]) -> Vec<u8> {
+        let input = [b"{number,", value, b",", pattern, b"}"].concat();
+        eval(locale, &input).unwrap()
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(
+            number(b"en", b"1234567.891", b"#\\,##0.00"),
+            b"1,234,567.89"
+        );
+        assert_eq!(number(b"en", b"-1234", b"#\\,##0"), b"-1,234");
+        assert_eq!(number(b"en", b"0.5", b"0.00"), b"0.50");
+        assert_eq!(number(b"en", b"0.125", b"0.##"), b"0.13");
+        assert_eq!(number(b"en", b"-2.5", b"0"), b"-3");
+        assert_eq!(number(b"en", b"0", b"#"), b"0");
+        assert_eq!(number(b"en", b"0.4", b"#"), b"0");
+        assert_eq!(number(b"en", b"0.5", b"#.#"), b"0.5");
+        assert_eq!(number(b"en", b"0.135", b"0.##"), b"0.14");
+        assert_eq!(number(b"en", b"9.996", b"0.00"), b"10.00");
+        assert_eq!(number(b"en", b"7", b"000"), b"007");
+        assert_eq!(number(b"en", b"0.256", b"0.#%"), b"25.6%");
+        assert_eq!(number(b"en", b"-0.001", b"0.00"), b"0.00");
+        assert_eq!(
+            number(b"en", b"{div,1,3}", b"$#\\,##0.00 USD"),
+            b"$0.33 USD"
+        );
+        assert_eq!(number(b"en", b"12345678", b"#\\,##\\,##0"), b"1,23,45,678");
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(eval(b"en", b"{number,1234.5}"), Ok(b"1,234.5".to_vec()));
+        assert_eq!(eval(b"de", b"{number,1234.5}"), Ok(b"1.234,5".to_vec()));
+        let expected = "1\u{202f}234,5".as_bytes().to_vec();
+        assert_eq!(eval(b"fr", b"{number,1234.5}"), Ok(expected));
+    }
+
+    #[test]
+    fn number_errors() {
+        assert_eq!(eval(b"en", b"{number,x}"), Err((8, "expected a number")));
+        let tokens = tokenize_to_vec(b"{number,{$x}}").unwrap();
+        for v in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut context = BTreeMap::new();
+            context.insert(b"x".to_vec(), Value::Float(v));
+            let env = Environment::standard();
+            assert_eq!(
+                env.eval_with(&tokens, &context),
+                Err((8, "expected a number"))
+            );
+        }
+        let input = b"{number,1,abc}";
+        assert_eq!(eval(b"en", input), Err((10, "invalid number pattern")));
+        let input = b"{number,1,0#.0}";
+        assert_eq!(eval(b"en", input), Err((10, "invalid number pattern")));
+    }
+
+    #[test]
+    fn civil() {
+        let t = DateTime::from_unix(0);
+        assert_eq!(
+            (t.year, t.month, t.day, t.weekday, t.day_of_year),
+            (1970, 1, 1, 4, 1)
+        );
+        // a leap day
+        let t = DateTime::from_unix(951_782_400);
+        assert_eq!((t.year, t.month, t.day, t.day_of_year), (2000, 2, 29, 60));
+        let t = DateTime::from_unix(-1);
+        assert_eq!(
+            (t.year, t.month, t.day, t.hour, t.minute, t.second),
+            (1969, 12, 31, 23, 59, 59)
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            eval(b"en", b"{date,1700000000}"),
+            Ok(b"2023-11-14T22:13:20Z".to_vec())
+        );
+        let input = b"{date,1700000000,%a %e %B %Y %I:%M %p (%j) %%}";
+        assert_eq!(
+            eval(b"en", input),
+            Ok(b"Tue 14 November 2023 10:13 PM (318) %".to_vec())
+        );
+        let input = b"{date,86400,%F %T %Z}";
+        assert_eq!(eval(b"en", input), Ok(b"1970-01-02 00:00:00 UTC".to_vec()));
+        assert_eq!(eval(b"en", b"{date,1.5}"), Err((6, "expected an integer")));
+        let input = b"{date,0,%Y %Q}";
+        assert_eq!(
+            eval(b"en", input),
+            Err((8, "unknown date format specifier"))
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! message formatting for localized text, like ICU's MessageFormat. the plural
+//! categories follow the CLDR rules for each built in locale
+
+use alloc::vec::Vec;
+
+use super::math::Number;
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+/// the built in locales. a region or script in a tag is ignored, except that
+/// `pt-PT` has its own plural rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    Ar,
+    Cs,
+    De,
+    #[default]
+    En,
+    Es,
+    Fr,
+    It,
+    Ja,
+    Ko,
+    Nl,
+    Pl,
+    Pt,
+    PtPt,
+    Ru,
+    Sv,
+    Uk,
+    Zh,
+}
+
+impl Locale {
+    /// from a tag like `en`, `en-US` or `pt_PT`. case insensitive
+    pub fn from_tag(tag: &[u8]) -> Option<Locale> {
+        let mut parts = tag.split(|c| *c == b'-' || *c == b'_');
+        let language = parts.next()?.to_ascii_lowercase();
+        let region = parts.next().map(|v| v.to_ascii_uppercase());
+        Some(match language.as_slice() {
+            b"ar" => Locale::Ar,
+            b"cs" => Locale::Cs,
+            b"de" => Locale::De,
+            b"en" => Locale::En,
+            b"es" => Locale::Es,
+            b"fr" => Locale::Fr,
+            b"it" => Locale::It,
+            b"ja" => Locale::Ja,
+            b"ko" => Locale::Ko,
+            b"nl" => Locale::Nl,
+            b"pl" => Locale::Pl,
+            b"pt" if region.as_deref() == Some(b"PT") => Locale::PtPt,
+            b"pt" => Locale::Pt,
+            b"ru" => Locale::Ru,
+            b"sv" => Locale::Sv,
+            b"uk" => Locale::Uk,
+            b"zh" => Locale::Zh,
+            _ => return None,
+        })
+    }
+
+    /// the cardinal category, as used for `{plural}`
+    pub fn plural(self, n: &Operands) -> Category {
+        let int = n.is_integer();
+        // i % 1000000 = 0 and v = 0, for "a million of"
+        let million = n.v == 0 && n.i != 0 && n.i.is_multiple_of(1_000_000);
+        match self {
+            Locale::Ja | Locale::Ko | Locale::Zh => Category::Other,
+            Locale::De | Locale::En | Locale::Nl | Locale::Sv => {
+                if n.i == 1 && n.v == 0 {
+                    Category::One
+                } else {
+                    Category::Other
+                }
+            }
+            Locale::It | Locale::PtPt => {
+                if n.i == 1 && n.v == 0 {
+                    Category::One
+                } else if million {
+                    Category::Many
+                } else {
+                    Category::Other
+                }
+            }
+            Locale::Es => {
+                if int && n.i == 1 {
+                    Category::One
+                } else if million {
+                    Category::Many
+                } else {
+                    Category::Other
+                }
+            }
+            Locale::Fr | Locale::Pt => {
+                if n.i <= 1 {
+                    Category::One
+                } else if million {
+                    Category::Many
+                } else {
+                    Category::Other
+                }
+            }
+            Locale::Ru | Locale::Uk => {
+                let (i10, i100) = (n.i % 10, n.i % 100);
+                if n.v != 0 {
+                    Category::Other
+                } else if i10 == 1 && i100 != 11 {
+                    Category::One
+                } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+                    Category::Few
+                } else {
+                    Category::Many
+                }
+            }
+            Locale::Pl => {
+                let (i10, i100) = (n.i % 10, n.i % 100);
+                if n.v != 0 {
+                    Category::Other
+                } else if n.i == 1 {
+                    Category::One
+                } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+                    Category::Few
+                } else {
+                    Category::Many
+                }
+            }
+            Locale::Cs => {
+                if n.v != 0 {
+                    Category::Many
+                } else if n.i == 1 {
+                    Category::One
+                } else if (2..=4).contains(&n.i) {
+                    Category::Few
+                } else {
+                    Category::Other
+                }
+            }
+            Locale::Ar => {
+                let n100 = n.i % 100;
+                if !int {
+                    Category::Other
+                } else if n.i == 0 {
+                    Category::Zero
+                } else if n.i == 1 {
+                    Category::One
+                } else if n.i == 2 {
+                    Category::Two
+                } else if (3..=10).contains(&n100) {
+                    Category::Few
+                } else if (11..=99).contains(&n100) {
+                    Category::Many
+                } else {
+                    Category::Other
+                }
+            }
+        }
+    }
+
+    /// the ordinal category, as used for `{ordinal}`
+    pub fn ordinal(self, n: &Operands) -> Category {
+        if !n.is_integer() {
+            return Category::Other;
+        }
+        let (n10, n100) = (n.i % 10, n.i % 100);
+        match self {
+            Locale::En => match (n10, n100) {
+                (1, v) if v != 11 => Category::One,
+                (2, v) if v != 12 => Category::Two,
+                (3, v) if v != 13 => Category::Few,
+                _ => Category::Other,
+            },
+            Locale::Fr if n.i == 1 => Category::One,
+            Locale::It if matches!(n.i, 8 | 11 | 80 | 800) => Category::Many,
+            Locale::Sv if (n10 == 1 || n10 == 2) && n100 != 11 && n100 != 12 => Category::One,
+            _ => Category::Other,
+        }
+    }
+
+    /// the usual way to write an ordinal number, for `{ordinal,n}`
+    fn write_ordinal(self, n: &Operands, number: &[u8], out: &mut Vec<u8>) {
+        let category = self.ordinal(n);
+        let (prefix, suffix): (&str, &str) = match self {
+            Locale::En => match category {
+                Category::One => ("", "st"),
+                Category::Two => ("", "nd"),
+                Category::Few => ("", "rd"),
+                _ => ("", "th"),
+            },
+            Locale::Fr if category == Category::One => ("", "er"),
+            Locale::Fr | Locale::Nl => ("", "e"),
+            Locale::Sv if category == Category::One => ("", ":a"),
+            Locale::Sv => ("", ":e"),
+            Locale::Cs | Locale::De | Locale::Pl => ("", "."),
+            Locale::Es | Locale::It | Locale::Pt | Locale::PtPt => ("", "º"),
+            Locale::Ru | Locale::Uk => ("", "-й"),
+            Locale::Ja => ("", "番目"),
+            Locale::Ko => ("", "번째"),
+            Locale::Zh => ("第", ""),
+            Locale::Ar => ("", ""),
+        };
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(number);
+        out.extend_from_slice(suffix.as_bytes());
+    }
+}
+
+/// the CLDR plural categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Zero,
+    One,
+    Two,
+    Few,
+    Many,
+    Other,
+}
+
+impl Category {
+    /// as written in a message, like `one`
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            Category::Zero => b"zero",
+            Category::One => b"one",
+            Category::Two => b"two",
+            Category::Few => b"few",
+            Category::Many => b"many",
+            Category::Other => b"other",
+        }
+    }
+}
+
+/// the parts of a number which the plural rules look at. these come from the
+/// text, since `1` and `1.0` can be in different categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operands {
+    /// the integer digits. saturates if too large
+    pub i: u64,
+    /// the number of visible fraction digits, with trailing zeros
+    pub v: usize,
+    /// the visible fraction digits as an integer. saturates if too large
+    pub f: u64,
+}
+
+impl Operands {
+    /// from text like `-1.50`. the sign is ignored
+    pub fn parse(text: &[u8]) -> Option<Operands> {
+        let text = text.trim_ascii();
+        let text = match text.first() {
+            Some(b'-') | Some(b'+') => &text[1..],
+            _ => text,
+        };
+        let (whole, fraction) = match text.iter().position(|c| *c == b'.') {
+            Some(i) => (&text[..i], &text[i + 1..]),
+            None => (text, &text[text.len()..]),
+        };
+        if whole.is_empty() || (fraction.is_empty() && whole.len() != text.len()) {
+            return None;
+        }
+        fn digits(text: &[u8]) -> Option<u64> {
+            text.iter().try_fold(0u64, |acc, c| {
+                if !c.is_ascii_digit() {
+                    return None;
+                }
+                Some(acc.saturating_mul(10).saturating_add((c - b'0') as u64))
+            })
+        }
+        Some(Operands {
+            i: digits(whole)?,
+            v: fraction.len(),
+            f: digits(fraction)?,
+        })
+    }
+
+    /// true if there isn't a non zero fraction, like `1` or `1.00`
+    pub fn is_integer(&self) -> bool {
+        self.f == 0
+    }
+}
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"plural", plural);
+    env.register(b"select", select);
+    env.register(b"ordinal", ordinal);
+}
+
+impl<'e, 't, 'a> Evaluation<'e, 't, 'a> {
+    /// evaluates arg n of the call as the operands of a number
+    fn arg_operands(&mut self, call: &Call, n: usize) -> Result<(Vec<u8>, Operands), EvalError> {
+        let text = self.arg_bytes(call, n)?;
+        match Operands::parse(&text) {
+            Some(v) => Ok((text, v)),
+            None => Err((self.arg_offset(call, n), "expected a number")),
+        }
+    }
+}
+
+/// the args after the first are pairs of key and message. this evaluates each
+/// key
+fn keys(ev: &mut Evaluation, call: &Call) -> Result<Vec<Vec<u8>>, EvalError> {
+    if call.num_args % 2 != 1 {
+        return Err((call.offset, "wrong number of arguments"));
+    }
+    let mut keys = Vec::with_capacity(call.num_args / 2);
+    for n in (1..call.num_args).step_by(2) {
+        keys.push(ev.arg_bytes(call, n)?);
+    }
+    Ok(keys)
+}
+
+/// evaluates the message for the key at some index, else for `other`
+fn pick(
+    ev: &mut Evaluation,
+    call: &Call,
+    keys: &[Vec<u8>],
+    found: Option<usize>,
+) -> Result<Value, EvalError> {
+    match found.or_else(|| keys.iter().position(|k| k == b"other")) {
+        Some(k) => ev.arg(call, 2 + 2 * k),
+        None => Err((call.offset, "no matching case and no other")),
+    }
+}
+
+/// `{plural,count,key,message,...}`. a key is a plural category like `one`,
+/// or an exact value like `=0` which takes priority
+fn plural(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let (count, operands) = ev.arg_operands(call, 0)?;
+    let keys = keys(ev, call)?;
+    let category = ev.locale().plural(&operands);
+    // compared by value, so `=0` matches `0.0`
+    let count = Number::parse(&count).map(Number::as_f64);
+    let exact = keys.iter().position(|k| match k.strip_prefix(b"=") {
+        Some(v) => count.is_some() && Number::parse(v).map(Number::as_f64) == count,
+        None => false,
+    });
+    let found = exact.or_else(|| keys.iter().position(|k| k == category.name()));
+    pick(ev, call, &keys, found)
+}
+
+/// `{select,value,key,message,...}`. the message for the key equal to the
+/// value, else for `other`
+fn select(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let value = ev.arg_bytes(call, 0)?;
+    let keys = keys(ev, call)?;
+    let found = keys.iter().position(|k| *k == value);
+    pick(ev, call, &keys, found)
+}
+
+/// `{ordinal,n}` writes n as an ordinal, like `1st`. with more args,
+/// `{ordinal,n,key,message,...}` selects by the ordinal category instead, like
+/// `plural`
+fn ordinal(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let (number, operands) = ev.arg_operands(call, 0)?;
+    let locale = ev.locale();
+    if call.num_args == 1 {
+        let mut out = Vec::new();
+        locale.write_ordinal(&operands, number.trim_ascii(), &mut out);
+        return Ok(Value::Str(out));
+    }
+    let keys = keys(ev, call)?;
+    let category = locale.ordinal(&operands);
+    let found = keys.iter().position(|k| k == category.name());
+    pick(ev, call, &keys, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn eval(locale: &[u8], input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let mut env = Environment::standard();
+        env.set_locale(Locale::from_tag(locale).unwrap());
+        env.eval(&tokens)
+    }
+
+    fn plurals(locale: &[u8], numbers: &[&[u8]]) -> Vec<Category> {
+        let locale = Locale::from_tag(locale).unwrap();
+        let numbers = numbers.iter();
+        numbers
+            .map(|n| locale.plural(&Operands::parse(n).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(Locale::from_tag(b"en-US"), Some(Locale::En));
+        assert_eq!(Locale::from_tag(b"PT_pt"), Some(Locale::PtPt));
+        assert_eq!(Locale::from_tag(b"pt-BR"), Some(Locale::Pt));
+        assert_eq!(Locale::from_tag(b"zz"), None);
+    }
+
+    #[test]
+    fn operands() {
+        let parse = Operands::parse;
+        assert_eq!(parse(b"-1.50"), Some(Operands { i: 1, v: 2, f: 50 }));
+        assert_eq!(parse(b"3"), Some(Operands { i: 3, v: 0, f: 0 }));
+        assert_eq!(parse(b"1."), None);
+        assert_eq!(parse(b"x"), None);
+    }
+
+    #[test]
+    fn cardinal_rules() {
+        use Category::*;
+        assert_eq!(
+            plurals(b"en", &[b"1", b"1.0", b"0", b"2"]),
+            [One, Other, Other, Other]
+        );
+        assert_eq!(
+            plurals(b"fr", &[b"0", b"1.5", b"2", b"1000000"]),
+            [One, One, Other, Many]
+        );
+        assert_eq!(
+            plurals(b"ru", &[b"1", b"21", b"11", b"3", b"13", b"5", b"1.5"]),
+            [One, One, Many, Few, Many, Many, Other]
+        );
+        assert_eq!(
+            plurals(b"pl", &[b"1", b"22", b"21", b"12"]),
+            [One, Few, Many, Many]
+        );
+        assert_eq!(
+            plurals(b"cs", &[b"1", b"3", b"5", b"1.5"]),
+            [One, Few, Other, Many]
+        );
+        assert_eq!(
+            plurals(b"ar", &[b"0", b"1", b"2", b"103", b"111", b"100"]),
+            [Zero, One, Two, Few, Many, Other]
+        );
+        assert_eq!(plurals(b"ja", &[b"1"]), [Other]);
+    }
+
+    #[test]
+    fn plural() {
+        let message = b"{plural,{$n},=0,no files,one,a file,other,{$n} files}";
+        for (locale, n, expected) in [
+            (&b"en"[..], &b"0"[..], &b"no files"[..]),
+            (b"en", b"1", b"a file"),
+            (b"en", b"1.0", b"1.0 files"),
+            (b"en", b"7", b"7 files"),
+            (b"fr", b"0.0", b"no files"),
+            (b"fr", b"1.5", b"a file"),
+        ] {
+            let input = [b"{let,n,", n, b",", message, b"}"].concat();
+            assert_eq!(eval(locale, &input), Ok(expected.to_vec()));
+        }
+        let input = b"{plural,2,one,a,few,b}";
+        assert_eq!(
+            eval(b"en", input),
+            Err((0, "no matching case and no other"))
+        );
+        assert_eq!(eval(b"ru", input), Ok(b"b".to_vec()));
+        assert_eq!(
+            eval(b"en", b"{plural,x,other,a}"),
+            Err((8, "expected a number"))
+        );
+        assert_eq!(
+            eval(b"en", b"{plural,1,one}"),
+            Err((0, "wrong number of arguments"))
+        );
+    }
+
+    #[test]
+    fn select() {
+        let input = b"{select,{$g},male,he,female,she,other,they}";
+        for (g, expected) in [
+            (&b"male"[..], &b"he"[..]),
+            (b"female", b"she"),
+            (b"x", b"they"),
+        ] {
+            let input = [b"{let,g,", g, b",", input, b"}"].concat();
+            assert_eq!(eval(b"en", &input), Ok(expected.to_vec()));
+        }
+        // only the chosen message is evaluated
+        assert_eq!(
+            eval(b"en", b"{select,a,a,1,other,{nope}}"),
+            Ok(b"1".to_vec())
+        );
+    }
+
+    #[test]
+    fn ordinal() {
+        let input = b"{ordinal,1} {ordinal,2} {ordinal,3} {ordinal,11} {ordinal,22} {ordinal,113}";
+        assert_eq!(
+            eval(b"en", input),
+            Ok(b"1st 2nd 3rd 11th 22nd 113th".to_vec())
+        );
+        let input = b"{ordinal,1} {ordinal,2}";
+        assert_eq!(eval(b"fr", input), Ok(b"1er 2e".to_vec()));
+        assert_eq!(eval(b"de", input), Ok(b"1. 2.".to_vec()));
+        assert_eq!(eval(b"sv", input), Ok(b"1:a 2:a".to_vec()));
+        let input = b"{ordinal,23,one,st,two,nd,few,rd,other,th}";
+        assert_eq!(eval(b"en", input), Ok(b"rd".to_vec()));
+    }
+}
@@ -0,0 +1,248 @@
+//! functions on lists. `map` and `filter` evaluate their template once per
+//! item, with the item bound to `it`
+
+use alloc::vec::Vec;
+
+use super::math::Number;
+use super::{Call, Environment, EvalError, Evaluation, ListBuilder, TextBuilder, Value};
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"list", list);
+    env.register(b"len", len);
+    env.register(b"count", count);
+    env.register(b"split", split);
+    env.register(b"join", join);
+    env.register(b"map", map);
+    env.register(b"filter", filter);
+    env.register(b"foreach", foreach);
+    env.register(b"range", range);
+    env.register(b"nth", nth);
+}
+
+/// `{list,...}`. each arg is an item
+fn list(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    let mut items = ListBuilder::new(call);
+    for n in 0..call.num_args {
+        let item = ev.arg(call, n)?;
+        items.push(ev, item)?;
+    }
+    Ok(items.finish())
+}
+
+/// `{len,x}`. the number of items in a list, or else the length of the text
+fn len(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 1)?;
+    let len = match ev.arg(call, 0)? {
+        Value::List(items) => items.len(),
+        v => v.text_len(),
+    };
+    Ok(Value::Int(len as i64))
+}
+
+/// `{count,list}`. the number of items
+fn count(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 1)?;
+    Ok(Value::Int(ev.arg_list(call, 0)?.len() as i64))
+}
+
+/// `{split,text,sep}`. empty text gives an empty list, and an empty separator
+/// splits between each byte
+fn split(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let text = ev.arg_bytes(call, 0)?;
+    let sep = ev.arg_bytes(call, 1)?;
+    let mut items = ListBuilder::new(call);
+    if text.is_empty() {
+        return Ok(items.finish());
+    }
+    if sep.is_empty() {
+        for c in &text {
+            items.push(ev, Value::Str(alloc::vec![*c]))?;
+        }
+        return Ok(items.finish());
+    }
+    let mut begin = 0;
+    let mut i = 0;
+    while i + sep.len() <= text.len() {
+        if text[i..].starts_with(&sep) {
+            items.push(ev, Value::Str(text[begin..i].to_vec()))?;
+            i += sep.len();
+            begin = i;
+        } else {
+            i += 1;
+        }
+    }
+    items.push(ev, Value::Str(text[begin..].to_vec()))?;
+    Ok(items.finish())
+}
+
+/// `{join,list}` or `{join,list,sep}`. the items as text with the separator
+/// between each
+fn join(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 2)?;
+    let items = ev.arg_list(call, 0)?;
+    let sep = if call.num_args == 2 {
+        ev.arg_bytes(call, 1)?
+    } else {
+        Vec::new()
+    };
+    let mut out = TextBuilder::new(call);
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            out.push_bytes(ev, &sep)?;
+        }
+        out.push_value(ev, item)?;
+    }
+    Ok(out.finish())
+}
+
+/// `{map,list,template}`. a list of the template's value for each item
+fn map(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let items = ev.arg_list(call, 0)?;
+    let mut ret = ListBuilder::new(call);
+    for item in items {
+        let value = ev.arg_with(call, 1, b"it", item)?;
+        ret.push(ev, value)?;
+    }
+    Ok(ret.finish())
+}
+
+/// `{filter,list,predicate}`. the items for which the predicate is true
+fn filter(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let items = ev.arg_list(call, 0)?;
+    let mut ret = ListBuilder::new(call);
+    for item in items {
+        if ev.arg_with(call, 1, b"it", item.clone())?.truthy() {
+            ret.push(ev, item)?;
+        }
+    }
+    Ok(ret.finish())
+}
+
+/// `{foreach,list,var,body}`. the body's text for each item, one after
+/// another. the item is bound to var
+fn foreach(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(3, 3)?;
+    let items = ev.arg_list(call, 0)?;
+    let name = ev.arg_bytes(call, 1)?;
+    let mut out = TextBuilder::new(call);
+    for item in items {
+        let value = ev.arg_with(call, 2, &name, item)?;
+        out.push_value(ev, &value)?;
+    }
+    Ok(out.finish())
+}
+
+/// `{range,start,end}`. integers from start up to but not including end
+fn range(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let mut bounds = [0i64; 2];
+    for (n, bound) in bounds.iter_mut().enumerate() {
+        *bound = match ev.arg(call, n)?.as_number() {
+            Some(Number::Int(v)) => v,
+            _ => return Err((ev.arg_offset(call, n), "expected an integer")),
+        };
+    }
+    let [start, end] = bounds;
+    let len = usize::try_from(end.saturating_sub(start)).unwrap_or(0);
+    // each item is a step, and the checks happen before the allocation
+    ev.consume_fuel(call.offset, len)?;
+    ev.check_output(call.offset, len)?;
+    Ok(Value::List((start..end).map(Value::Int).collect()))
+}
+
+/// `{nth,list,i}`. the item at index i, starting from 0
+fn nth(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let mut items = ev.arg_list(call, 0)?;
+    let index = ev.arg_index(call, 1)?;
+    if index >= items.len() {
+        return Err((ev.arg_offset(call, 1), "index out of range"));
+    }
+    Ok(items.swap_remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{Limits, OUTPUT_LIMIT_REACHED};
+    use crate::tokenize_to_vec;
+    use alloc::collections::BTreeMap;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        Environment::standard().eval(&tokens)
+    }
+
+    #[test]
+    fn split_join() {
+        assert_eq!(eval(b"{count,{split,a;b;;c,;}}"), Ok(b"4".to_vec()));
+        assert_eq!(eval(b"{join,{split,a::b,::},-}"), Ok(b"a-b".to_vec()));
+        assert_eq!(eval(b"{join,{split,abc,}, }"), Ok(b"a b c".to_vec()));
+        assert_eq!(eval(b"{count,{split,,;}}"), Ok(b"0".to_vec()));
+        assert_eq!(eval(b"{join,{list,1,2}}"), Ok(b"12".to_vec()));
+        assert_eq!(eval(b"{join,abc,-}"), Err((6, "expected a list")));
+    }
+
+    #[test]
+    fn templates() {
+        let input = b"{join,{map,{split,a b, },<{$it}>}}";
+        assert_eq!(eval(input), Ok(b"<a><b>".to_vec()));
+        let input = b"{filter,{range,0,10},{eq,{mod,{$it},3},0}}";
+        assert_eq!(eval(input), Ok(b"0,3,6,9".to_vec()));
+        let input = b"{foreach,{list,x,y},item,- {$item}\n}";
+        assert_eq!(eval(input), Ok(b"- x\n- y\n".to_vec()));
+        // the template isn't evaluated for an empty list
+        assert_eq!(eval(b"{map,{list},{nope}}"), Ok(b"".to_vec()));
+    }
+
+    #[test]
+    fn output_limit() {
+        // each item is checked as it's added, so the list never grows far past
+        // the limit
+        let mut env = Environment::standard();
+        env.set_limits(Limits {
+            fuel: usize::MAX,
+            ..env.limits()
+        });
+        let big = b"{let,big,{repeat,x,500000},";
+        for (template, offset) in [
+            (&b"{count,{map,{range,0,2000},{$big}}}"[..], 34),
+            (b"{count,{list,{$big},{$big},{$big}}}", 34),
+            (b"{join,{list,{$big},{$big}},{$big}}", 27),
+            (b"{foreach,{range,0,2000},x,{$big}}", 27),
+        ] {
+            let mut input = big.to_vec();
+            input.extend_from_slice(template);
+            input.push(b'}');
+            let tokens = tokenize_to_vec(&input).unwrap();
+            assert_eq!(env.eval(&tokens), Err((offset, OUTPUT_LIMIT_REACHED)));
+        }
+    }
+
+    #[test]
+    fn range_nth() {
+        assert_eq!(eval(b"{range,-1,2}|{range,2,1}"), Ok(b"-1,0,1|".to_vec()));
+        assert_eq!(eval(b"{range,0,1.5}"), Err((9, "expected an integer")));
+        assert_eq!(eval(b"{nth,{range,5,9},2}"), Ok(b"7".to_vec()));
+        assert_eq!(eval(b"{nth,{list,a},1}"), Err((14, "index out of range")));
+        let input = b"{range,0,99999999999}";
+        assert_eq!(eval(input), Err((0, crate::eval::FUEL_EXHAUSTED)));
+    }
+
+    #[test]
+    fn notification() {
+        let tokens = tokenize_to_vec(
+            b"{$name} has {count,{$items}} items:{foreach,{$items},i,\n{add,{$i},1}}",
+        )
+        .unwrap();
+        let mut context = BTreeMap::new();
+        context.insert(b"name".to_vec(), Value::Str(b"Bob".to_vec()));
+        let items = Value::List(alloc::vec![Value::Int(1), Value::Int(2)]);
+        context.insert(b"items".to_vec(), items);
+        let output = Environment::standard().eval_with(&tokens, &context);
+        assert_eq!(output, Ok(b"Bob has 2 items:\n2\n3".to_vec()));
+    }
+}
@@ -0,0 +1,371 @@
+//! arithmetic. args are parsed as integers, like `-12`, or decimals, like
+//! `1.5`. integer math stays exact, and anything involving a decimal is done
+//! in floating point
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+const OUT_OF_RANGE: &str = "number out of range";
+
+impl Number {
+    /// an optional sign, digits, then optionally a `.` and more digits.
+    /// surrounding whitespace is ignored
+    pub(crate) fn parse(text: &[u8]) -> Option<Number> {
+        let text = text.trim_ascii();
+        let digits = match text.first() {
+            Some(b'-') | Some(b'+') => &text[1..],
+            _ => text,
+        };
+        let (whole, fraction) = match digits.iter().position(|c| *c == b'.') {
+            Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+            None => (digits, None),
+        };
+        if whole.is_empty() || !whole.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        // the text is all ascii at this point
+        let text = core::str::from_utf8(text).ok()?;
+        match fraction {
+            None => match text.parse::<i64>() {
+                Ok(v) => Some(Number::Int(v)),
+                // too large for an integer
+                Err(_) => text.parse::<f64>().ok().map(Number::Float),
+            },
+            Some(fraction) => {
+                if fraction.is_empty() || !fraction.iter().all(u8::is_ascii_digit) {
+                    return None;
+                }
+                text.parse::<f64>().ok().map(Number::Float)
+            }
+        }
+    }
+
+    pub(super) fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(v) => v as f64,
+            Number::Float(v) => v,
+        }
+    }
+
+    /// integers in decimal. floats with the shortest text that parses back to
+    /// the same value, without an exponent
+    pub(super) fn render(self) -> Vec<u8> {
+        match self {
+            Number::Int(v) => v.to_string().into_bytes(),
+            Number::Float(v) => v.to_string().into_bytes(),
+        }
+    }
+
+    fn cmp(self, other: Number) -> Ordering {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+            // nan isn't possible since results are checked to be finite
+            (a, b) => a
+                .as_f64()
+                .partial_cmp(&b.as_f64())
+                .unwrap_or(Ordering::Equal),
+        }
+    }
+}
+
+/// gives an error instead of a result which can't be rendered
+fn finite(offset: usize, value: f64) -> Result<Number, EvalError> {
+    if !value.is_finite() {
+        return Err((offset, OUT_OF_RANGE));
+    }
+    Ok(Number::Float(value))
+}
+
+/// rounds half away from zero
+fn round_f64(value: f64) -> f64 {
+    // at or beyond this, every float is already a whole number
+    const WHOLE: f64 = (1u64 << 52) as f64;
+    if !(-WHOLE..=WHOLE).contains(&value) {
+        return value;
+    }
+    let truncated = value as i64 as f64;
+    let remainder = value - truncated;
+    if remainder >= 0.5 {
+        truncated + 1.0
+    } else if remainder <= -0.5 {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"add", add);
+    env.register(b"sub", sub);
+    env.register(b"mul", mul);
+    env.register(b"div", div);
+    env.register(b"mod", mod_);
+    env.register(b"min", min);
+    env.register(b"max", max);
+    env.register(b"abs", abs);
+    env.register(b"round", round);
+    env.register(b"lt", lt);
+    env.register(b"gt", gt);
+}
+
+impl<'e, 't, 'a> Evaluation<'e, 't, 'a> {
+    /// evaluates arg n of the call as a number
+    pub(super) fn arg_number(&mut self, call: &Call, n: usize) -> Result<Number, EvalError> {
+        match self.arg(call, n)?.as_number() {
+            Some(v) => Ok(v),
+            None => Err((self.arg_offset(call, n), "expected a number")),
+        }
+    }
+}
+
+/// applies the operation left to right over all the args
+fn fold(
+    ev: &mut Evaluation,
+    call: &Call,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let mut acc = ev.arg_number(call, 0)?;
+    for n in 1..call.num_args {
+        let rhs = ev.arg_number(call, n)?;
+        acc = match (acc, rhs) {
+            (Number::Int(a), Number::Int(b)) => match int(a, b) {
+                Some(v) => Number::Int(v),
+                None => return Err((call.offset, OUT_OF_RANGE)),
+            },
+            (a, b) => finite(call.offset, float(a.as_f64(), b.as_f64()))?,
+        };
+    }
+    Ok(acc.into())
+}
+
+/// `{add,...}`
+fn add(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    fold(ev, call, i64::checked_add, |a, b| a + b)
+}
+
+/// `{sub,a,b}`
+fn sub(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    fold(ev, call, i64::checked_sub, |a, b| a - b)
+}
+
+/// `{mul,...}`
+fn mul(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    fold(ev, call, i64::checked_mul, |a, b| a * b)
+}
+
+/// `{div,a,b}`. an integer if it divides evenly
+fn div(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let lhs = ev.arg_number(call, 0)?;
+    let rhs = ev.arg_number(call, 1)?;
+    if rhs.as_f64() == 0.0 {
+        return Err((ev.arg_offset(call, 1), "division by zero"));
+    }
+    let ret = match (lhs, rhs) {
+        (Number::Int(a), Number::Int(b)) if a.checked_rem(b) == Some(0) => match a.checked_div(b) {
+            Some(v) => Number::Int(v),
+            None => return Err((call.offset, OUT_OF_RANGE)),
+        },
+        (a, b) => finite(call.offset, a.as_f64() / b.as_f64())?,
+    };
+    Ok(ret.into())
+}
+
+/// `{mod,a,b}`. has the sign of a
+fn mod_(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let lhs = ev.arg_number(call, 0)?;
+    let rhs = ev.arg_number(call, 1)?;
+    if rhs.as_f64() == 0.0 {
+        return Err((ev.arg_offset(call, 1), "division by zero"));
+    }
+    let ret = match (lhs, rhs) {
+        (Number::Int(a), Number::Int(b)) => match a.checked_rem(b) {
+            Some(v) => Number::Int(v),
+            None => return Err((call.offset, OUT_OF_RANGE)),
+        },
+        (a, b) => finite(call.offset, a.as_f64() % b.as_f64())?,
+    };
+    Ok(ret.into())
+}
+
+/// gives whichever arg is first in the ordering
+fn extreme(ev: &mut Evaluation, call: &Call, keep: Ordering) -> Result<Value, EvalError> {
+    call.expect_args(1, usize::MAX)?;
+    let mut ret = ev.arg_number(call, 0)?;
+    for n in 1..call.num_args {
+        let v = ev.arg_number(call, n)?;
+        if v.cmp(ret) == keep {
+            ret = v;
+        }
+    }
+    Ok(ret.into())
+}
+
+/// `{min,...}`
+fn min(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    extreme(ev, call, Ordering::Less)
+}
+
+/// `{max,...}`
+fn max(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    extreme(ev, call, Ordering::Greater)
+}
+
+/// `{abs,x}`
+fn abs(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 1)?;
+    let ret = match ev.arg_number(call, 0)? {
+        Number::Int(v) => match v.checked_abs() {
+            Some(v) => Number::Int(v),
+            None => return Err((call.offset, OUT_OF_RANGE)),
+        },
+        Number::Float(v) => Number::Float(if v < 0.0 { -v } else { v }),
+    };
+    Ok(ret.into())
+}
+
+/// `{round,x}` or `{round,x,digits}`. half away from zero, to some number of
+/// digits after the decimal point. default 0
+fn round(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 2)?;
+    let value = ev.arg_number(call, 0)?;
+    let digits = if call.num_args == 2 {
+        ev.arg_index(call, 1)?
+    } else {
+        0
+    };
+    let ret = match value {
+        Number::Int(_) => value,
+        Number::Float(v) if digits == 0 => {
+            let v = round_f64(v);
+            if v >= i64::MIN as f64 && v < i64::MAX as f64 {
+                Number::Int(v as i64)
+            } else {
+                Number::Float(v)
+            }
+        }
+        Number::Float(v) => {
+            // beyond this many digits, rounding doesn't change the value
+            if digits > 300 {
+                value
+            } else {
+                let scale = (0..digits).fold(1.0, |acc, _| acc * 10.0);
+                finite(call.offset, round_f64(v * scale) / scale)?
+            }
+        }
+    };
+    Ok(ret.into())
+}
+
+/// `{lt,a,b}`. `true` or `false`
+fn lt(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let lhs = ev.arg_number(call, 0)?;
+    let rhs = ev.arg_number(call, 1)?;
+    Ok(Value::Bool(lhs.cmp(rhs) == Ordering::Less))
+}
+
+/// `{gt,a,b}`. `true` or `false`
+fn gt(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let lhs = ev.arg_number(call, 0)?;
+    let rhs = ev.arg_number(call, 1)?;
+    Ok(Value::Bool(lhs.cmp(rhs) == Ordering::Greater))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        Environment::standard().eval(&tokens)
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Number::parse(b" -12 "), Some(Number::Int(-12)));
+        assert_eq!(Number::parse(b"+1.50"), Some(Number::Float(1.5)));
+        assert_eq!(
+            Number::parse(b"99999999999999999999"),
+            Some(Number::Float(1e20))
+        );
+        for text in [
+            &b""[..],
+            b"-",
+            b"1.",
+            b".5",
+            b"1e3",
+            b"1.2.3",
+            b"0x10",
+            b"a",
+        ] {
+            assert_eq!(Number::parse(text), None);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval(b"{add,1,2,3} {add,0.5,1}"), Ok(b"6 1.5".to_vec()));
+        assert_eq!(eval(b"{sub,1,3} {mul,2,-3,1.5}"), Ok(b"-2 -9".to_vec()));
+        assert_eq!(
+            eval(b"{div,6,3} {div,7,2} {div,-1,4}"),
+            Ok(b"2 3.5 -0.25".to_vec())
+        );
+        assert_eq!(eval(b"{mod,-7,3} {mod,7.5,2}"), Ok(b"-1 1.5".to_vec()));
+        assert_eq!(
+            eval(b"{min,3,1.5,2} {max,3,{add,2,2}}"),
+            Ok(b"1.5 4".to_vec())
+        );
+        assert_eq!(eval(b"{abs,-3} {abs,-0.5}"), Ok(b"3 0.5".to_vec()));
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(
+            eval(b"{round,2.5} {round,-2.5} {round,7}"),
+            Ok(b"3 -3 7".to_vec())
+        );
+        assert_eq!(
+            eval(b"{round,3.14159,2} {round,1.005,1}"),
+            Ok(b"3.14 1".to_vec())
+        );
+        assert_eq!(eval(b"{round,1,x}"), Err((9, "expected an index")));
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(
+            eval(b"{lt,1,2} {lt,2,1.5} {gt,10,9}"),
+            Ok(b"true false true".to_vec())
+        );
+        assert_eq!(
+            eval(b"{if,{gt,{add,1,1},1},big,small}"),
+            Ok(b"big".to_vec())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval(b"{add,1,x}"), Err((7, "expected a number")));
+        assert_eq!(eval(b"{add,1,}"), Err((7, "expected a number")));
+        assert_eq!(eval(b"{div,1,{sub,2,2}}"), Err((7, "division by zero")));
+        assert_eq!(eval(b"{mod,1,0.0}"), Err((7, "division by zero")));
+        assert_eq!(eval(b"{mul,9223372036854775807,2}"), Err((0, OUT_OF_RANGE)));
+        assert_eq!(eval(b"{sub,1}"), Err((0, "wrong number of arguments")));
+    }
+}
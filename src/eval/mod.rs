@@ -0,0 +1,556 @@
+//! evaluates tokens into output bytes. characters are sent through as is, and
+//! functions are looked up by name in an `Environment` and called. functions
+//! give a `Value`, which is only turned into text when needed
+//!
+//! builtins receive their arguments unevaluated, and ask for each one as
+//! needed. this lets an untaken branch skip all of its tokens (and errors)
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{function_args, Function, Token};
+
+mod control;
+mod definitions;
+mod format;
+mod intl;
+mod list;
+mod math;
+mod text;
+mod value;
+mod variables;
+
+pub use intl::{Category, Locale, Operands};
+pub(crate) use math::Number;
+pub use value::{Value, FALSE, TRUE};
+pub use variables::Context;
+
+/// on err, gives the offending location and error reason. same as `tokenize`
+pub type EvalError = (usize, &'static str);
+
+/// a function call being evaluated, as given to a builtin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    /// index of the FUNCTION token
+    pub index: usize,
+    /// offset of the function in the input
+    pub offset: usize,
+    pub num_args: usize,
+}
+
+impl Call {
+    /// gives an arity error if the number of args isn't within min and max (inclusive)
+    pub fn expect_args(&self, min: usize, max: usize) -> Result<(), EvalError> {
+        if self.num_args < min || self.num_args > max {
+            return Err((self.offset, "wrong number of arguments"));
+        }
+        Ok(())
+    }
+}
+
+/// reason given when the evaluation runs out of steps
+pub const FUEL_EXHAUSTED: &str = "fuel exhausted";
+/// reason given when function calls are nested too deeply
+pub const DEPTH_LIMIT_REACHED: &str = "depth limit reached";
+/// reason given when a value would be too large
+pub const OUTPUT_LIMIT_REACHED: &str = "output limit reached";
+
+/// bounds the resources used by an evaluation, since expressions can come from
+/// untrusted sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// the number of steps. each character and function call takes a step, and
+    /// builtins can take more for work which doesn't go through tokens
+    pub fuel: usize,
+    /// how many function calls can be nested. this bounds the native stack
+    /// used too, since each nested call recurses
+    pub depth: usize,
+    /// the maximum size of any value in bytes, including the final output
+    pub output: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000,
+            depth: 64,
+            output: 1 << 20,
+        }
+    }
+}
+
+/// a function callable from an expression. args are evaluated lazily through
+/// the `Evaluation`
+pub type Builtin = fn(&mut Evaluation<'_, '_, '_>, &Call) -> Result<Value, EvalError>;
+
+/// the set of functions available to expressions
+#[derive(Clone, Default)]
+pub struct Environment {
+    functions: BTreeMap<Vec<u8>, Builtin>,
+    limits: Limits,
+    locale: Locale,
+}
+
+impl Environment {
+    /// an environment without any functions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// applies to each evaluation separately
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// used for plurals and other localized formatting. default `en`
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
+
+    /// an environment with all the builtins
+    pub fn standard() -> Self {
+        let mut env = Self::new();
+        control::register(&mut env);
+        definitions::register(&mut env);
+        format::register(&mut env);
+        intl::register(&mut env);
+        list::register(&mut env);
+        math::register(&mut env);
+        text::register(&mut env);
+        variables::register(&mut env);
+        env
+    }
+
+    /// adds a function, replacing any existing function with the same name
+    pub fn register(&mut self, name: &[u8], builtin: Builtin) {
+        self.functions.insert(name.to_vec(), builtin);
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<Builtin> {
+        self.functions.get(name).copied()
+    }
+
+    /// names of all registered functions, in order
+    pub fn names(&self) -> impl Iterator<Item = &[u8]> {
+        self.functions.keys().map(|k| k.as_slice())
+    }
+
+    /// evaluates tokens from a successful `tokenize`, without any variables
+    pub fn eval(&self, tokens: &[Token]) -> Result<Vec<u8>, EvalError> {
+        self.eval_with(tokens, &())
+    }
+
+    /// evaluates tokens from a successful `tokenize`. variables are looked up in
+    /// the context
+    pub fn eval_with(&self, tokens: &[Token], context: &dyn Context) -> Result<Vec<u8>, EvalError> {
+        let mut evaluation = Evaluation {
+            env: self,
+            tokens,
+            context,
+            bindings: Vec::new(),
+            definitions: Vec::new(),
+            frames: Vec::new(),
+            fuel: self.limits.fuel,
+            depth: 0,
+        };
+        Ok(evaluation.eval_range(0..tokens.len())?.into_bytes())
+    }
+}
+
+/// text which a builtin builds a piece at a time. the output limit is checked
+/// before each piece is added, so it bounds the memory used, not only the
+/// value returned
+pub struct TextBuilder {
+    offset: usize,
+    out: Vec<u8>,
+}
+
+impl TextBuilder {
+    pub fn new(call: &Call) -> Self {
+        Self {
+            offset: call.offset,
+            out: Vec::new(),
+        }
+    }
+
+    pub fn push_bytes(&mut self, ev: &Evaluation, bytes: &[u8]) -> Result<(), EvalError> {
+        ev.check_output(self.offset, self.out.len().saturating_add(bytes.len()))?;
+        self.out.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// adds the value as text
+    pub fn push_value(&mut self, ev: &Evaluation, value: &Value) -> Result<(), EvalError> {
+        ev.check_output(self.offset, self.out.len().saturating_add(value.text_len()))?;
+        value.write(&mut self.out);
+        Ok(())
+    }
+
+    pub fn finish(self) -> Value {
+        Value::Str(self.out)
+    }
+}
+
+/// a list which a builtin builds an item at a time. like `TextBuilder`, the
+/// output limit is checked for the list's text before each item is added
+pub struct ListBuilder {
+    offset: usize,
+    items: Vec<Value>,
+    /// the length of the list's text so far
+    len: usize,
+}
+
+impl ListBuilder {
+    pub fn new(call: &Call) -> Self {
+        Self {
+            offset: call.offset,
+            items: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, ev: &Evaluation, item: Value) -> Result<(), EvalError> {
+        let separator = usize::from(!self.items.is_empty());
+        let len = self.len.saturating_add(separator + item.text_len());
+        ev.check_output(self.offset, len)?;
+        self.len = len;
+        self.items.push(item);
+        Ok(())
+    }
+
+    pub fn finish(self) -> Value {
+        Value::List(self.items)
+    }
+}
+
+/// the state of a single evaluation
+pub struct Evaluation<'e, 't, 'a> {
+    env: &'e Environment,
+    tokens: &'t [Token<'a>],
+    context: &'e dyn Context,
+    /// from `let`. innermost last
+    bindings: Vec<(Vec<u8>, Value)>,
+    /// from `def`. the range of tokens for each body. ones made during a call
+    /// to a defined function are removed when it returns
+    definitions: Vec<(Vec<u8>, Range<usize>)>,
+    /// the evaluated args for each call to a defined function. innermost last
+    frames: Vec<Vec<Value>>,
+    /// steps remaining
+    fuel: usize,
+    /// number of function calls currently being evaluated
+    depth: usize,
+}
+
+impl<'e, 't, 'a> Evaluation<'e, 't, 'a> {
+    /// the range of tokens which make up arg n of the call
+    pub fn arg_range(&self, call: &Call, n: usize) -> Range<usize> {
+        match function_args(self.tokens, call.index).nth(n) {
+            Some(range) => range,
+            None => {
+                debug_assert!(false, "arg out of range");
+                call.index..call.index
+            }
+        }
+    }
+
+    /// where arg n of the call begins in the input. for an empty arg, this is
+    /// the separator after it
+    pub fn arg_offset(&self, call: &Call, n: usize) -> usize {
+        // an empty range still starts on its END_ARG
+        self.tokens[self.arg_range(call, n).start].offset()
+    }
+
+    /// the unevaluated tokens of arg n of the call
+    pub fn arg_tokens(&self, call: &Call, n: usize) -> &'t [Token<'a>] {
+        &self.tokens[self.arg_range(call, n)]
+    }
+
+    /// evaluates arg n of the call
+    pub fn arg(&mut self, call: &Call, n: usize) -> Result<Value, EvalError> {
+        let range = self.arg_range(call, n);
+        self.eval_range(range)
+    }
+
+    /// evaluates arg n of the call as text
+    pub fn arg_bytes(&mut self, call: &Call, n: usize) -> Result<Vec<u8>, EvalError> {
+        Ok(self.arg(call, n)?.into_bytes())
+    }
+
+    /// evaluates arg n of the call and gives its truthiness
+    pub fn arg_truthy(&mut self, call: &Call, n: usize) -> Result<bool, EvalError> {
+        Ok(self.arg(call, n)?.truthy())
+    }
+
+    /// evaluates arg n of the call as a non negative integer
+    pub fn arg_index(&mut self, call: &Call, n: usize) -> Result<usize, EvalError> {
+        match self.arg(call, n)?.as_index() {
+            Some(v) => Ok(v),
+            None => Err((self.arg_offset(call, n), "expected an index")),
+        }
+    }
+
+    /// evaluates arg n of the call as a list
+    pub fn arg_list(&mut self, call: &Call, n: usize) -> Result<Vec<Value>, EvalError> {
+        match self.arg(call, n)? {
+            Value::List(items) => Ok(items),
+            _ => Err((self.arg_offset(call, n), "expected a list")),
+        }
+    }
+
+    /// evaluates arg n of the call with a variable bound, like `let`
+    pub fn arg_with(
+        &mut self,
+        call: &Call,
+        n: usize,
+        name: &[u8],
+        value: Value,
+    ) -> Result<Value, EvalError> {
+        self.bindings.push((name.to_vec(), value));
+        let ret = self.arg(call, n);
+        self.bindings.pop();
+        ret
+    }
+
+    /// gives the value of a variable. `let` bindings shadow the context. the
+    /// value is a copy, so this takes a step for each byte of its text, and the
+    /// offset is for running out
+    pub fn lookup(&mut self, offset: usize, name: &[u8]) -> Result<Option<Value>, EvalError> {
+        let value = match self.bindings.iter().rev().find(|(k, _)| k == name) {
+            Some((_, v)) => Some(v.clone()),
+            None => {
+                let path: Vec<&[u8]> = name.split(|c| *c == b'.').collect();
+                self.context.lookup(&path)
+            }
+        };
+        if let Some(v) = &value {
+            self.consume_fuel(offset, v.text_len())?;
+        }
+        Ok(value)
+    }
+
+    /// the body of a function from `def`
+    fn definition(&self, name: &[u8]) -> Option<Range<usize>> {
+        self.definitions
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, body)| body.clone())
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.env.locale
+    }
+
+    /// takes steps from the limit. for builtins which do work proportional to
+    /// something other than their tokens
+    pub fn consume_fuel(&mut self, offset: usize, amount: usize) -> Result<(), EvalError> {
+        match self.fuel.checked_sub(amount) {
+            Some(v) => {
+                self.fuel = v;
+                Ok(())
+            }
+            None => Err((offset, FUEL_EXHAUSTED)),
+        }
+    }
+
+    /// errors if a value of this length would be too large. every value is
+    /// checked by the evaluator after the fact, but builtins must check first
+    /// to avoid a large allocation, like with `TextBuilder` and `ListBuilder`
+    pub fn check_output(&self, offset: usize, len: usize) -> Result<(), EvalError> {
+        if len > self.env.limits.output {
+            return Err((offset, OUTPUT_LIMIT_REACHED));
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, index: usize, function: &Function) -> Result<Value, EvalError> {
+        if self.depth >= self.env.limits.depth {
+            return Err((function.offset, DEPTH_LIMIT_REACHED));
+        }
+        self.depth += 1;
+        let ret = self.dispatch(index, function);
+        self.depth -= 1;
+        let ret = ret?;
+        self.check_output(function.offset, ret.text_len())?;
+        Ok(ret)
+    }
+
+    fn dispatch(&mut self, index: usize, function: &Function) -> Result<Value, EvalError> {
+        if let Some(name) = function.name.strip_prefix(b"$") {
+            // shorthand for {var,name}
+            if function.num_args != 0 {
+                return Err((function.offset, "wrong number of arguments"));
+            }
+            return match self.lookup(function.offset, name)? {
+                Some(v) => Ok(v),
+                None => Err((function.offset, "unknown variable")),
+            };
+        }
+        let call = Call {
+            index,
+            offset: function.offset,
+            num_args: function.num_args,
+        };
+        if let Some(body) = self.definition(function.name) {
+            return self.call_definition(&call, body);
+        }
+        match self.env.get(function.name) {
+            Some(builtin) => builtin(self, &call),
+            None => Err((function.offset, "unknown function")),
+        }
+    }
+
+    /// args are evaluated in the caller's frame, then the body in a new one
+    fn call_definition(&mut self, call: &Call, body: Range<usize>) -> Result<Value, EvalError> {
+        let mut args = Vec::with_capacity(call.num_args);
+        for n in 0..call.num_args {
+            args.push(self.arg(call, n)?);
+        }
+        self.frames.push(args);
+        let defined = self.definitions.len();
+        let ret = self.eval_range(body);
+        self.definitions.truncate(defined);
+        self.frames.pop();
+        ret
+    }
+
+    fn eval_range(&mut self, range: Range<usize>) -> Result<Value, EvalError> {
+        // a lone function call gives its value as is. anything else is text
+        if let Some(Token::Function(function)) = self.tokens.get(range.start) {
+            if range.start + function.delta == range.end {
+                self.consume_fuel(function.offset, 1)?;
+                return self.call(range.start, function);
+            }
+        }
+        let mut out = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            match self.tokens[i] {
+                Token::Character(character) => {
+                    self.consume_fuel(character.offset, 1)?;
+                    self.check_output(character.offset, out.len() + 1)?;
+                    out.push(character.val);
+                    i += 1;
+                }
+                Token::Function(function) => {
+                    self.consume_fuel(function.offset, 1)?;
+                    // the value was checked by call, so this is at most twice
+                    // the limit
+                    self.call(i, &function)?.write(&mut out);
+                    self.check_output(function.offset, out.len())?;
+                    i += function.delta;
+                }
+                Token::FunctionArgEnd(_) | Token::Invalid => {
+                    debug_assert!(false);
+                    return Err((0, "internal error"));
+                }
+            }
+        }
+        Ok(Value::Str(out))
+    }
+}
+
+/// a non negative decimal integer
+pub fn parse_index(text: &[u8]) -> Option<usize> {
+    if text.is_empty() {
+        return None;
+    }
+    let mut ret = 0usize;
+    for c in text {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        ret = ret.checked_mul(10)?.checked_add((c - b'0') as usize)?;
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        Environment::standard().eval(&tokens)
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(eval(b"abc\\,"), Ok(b"abc,".to_vec()));
+    }
+
+    #[test]
+    fn unknown_function() {
+        assert_eq!(eval(b"ab{nope,1}"), Err((2, "unknown function")));
+    }
+
+    #[test]
+    fn values_between_calls() {
+        // the list is given to len as is, but stringified when mixed with text
+        assert_eq!(eval(b"{len,{list,a,bc,d}}"), Ok(b"3".to_vec()));
+        assert_eq!(eval(b"{len,x{list,a,bc,d}}"), Ok(b"7".to_vec()));
+        assert_eq!(
+            eval(b"{list,a,{add,1,2},{lt,1,2}}"),
+            Ok(b"a,3,true".to_vec())
+        );
+        assert_eq!(eval(b"{if,{sub,1,1},yes,no}"), Ok(b"no".to_vec()));
+        assert_eq!(eval(b"{if,{list},yes,no}"), Ok(b"no".to_vec()));
+        assert_eq!(
+            eval(b"{eq,{add,1,1},2}{eq,{list,1,2},1\\,2}"),
+            Ok(b"truetrue".to_vec())
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let tokens = tokenize_to_vec(b"ab{if,x,{if,x,cd}}").unwrap();
+        let mut env = Environment::standard();
+        let limits = env.limits();
+
+        env.set_limits(Limits { fuel: 5, ..limits });
+        assert_eq!(env.eval(&tokens), Err((12, FUEL_EXHAUSTED)));
+        env.set_limits(Limits { fuel: 8, ..limits });
+        assert_eq!(env.eval(&tokens), Ok(b"abcd".to_vec()));
+
+        env.set_limits(Limits { depth: 1, ..limits });
+        assert_eq!(env.eval(&tokens), Err((8, DEPTH_LIMIT_REACHED)));
+
+        env.set_limits(Limits {
+            output: 3,
+            ..limits
+        });
+        assert_eq!(env.eval(&tokens), Err((2, OUTPUT_LIMIT_REACHED)));
+        env.set_limits(Limits {
+            output: 1,
+            ..limits
+        });
+        assert_eq!(env.eval(&tokens), Err((1, OUTPUT_LIMIT_REACHED)));
+    }
+
+    #[test]
+    fn custom_builtin() {
+        fn reverse(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+            call.expect_args(1, 1)?;
+            let mut v = ev.arg_bytes(call, 0)?;
+            v.reverse();
+            Ok(Value::Str(v))
+        }
+        let tokens = tokenize_to_vec(b"{rev,ab{rev,cd}}").unwrap();
+        let mut env = Environment::new();
+        env.register(b"rev", reverse);
+        assert_eq!(env.eval(&tokens), Ok(b"cdba".to_vec()));
+        assert_eq!(
+            env.eval(&tokenize_to_vec(b"{rev}").unwrap()),
+            Err((0, "wrong number of arguments"))
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! functions on text
+
+use super::{Call, Environment, EvalError, Evaluation, TextBuilder, Value};
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"concat", concat);
+    env.register(b"repeat", repeat);
+}
+
+/// `{concat,...}`. the args one after another
+fn concat(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    let mut out = TextBuilder::new(call);
+    for i in 0..call.num_args {
+        let value = ev.arg(call, i)?;
+        out.push_value(ev, &value)?;
+    }
+    Ok(out.finish())
+}
+
+/// `{repeat,text,n}`. the text n times
+fn repeat(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(2, 2)?;
+    let text = ev.arg_bytes(call, 0)?;
+    let count = ev.arg_index(call, 1)?;
+    // checked before the allocation rather than after
+    ev.check_output(call.offset, text.len().saturating_mul(count))?;
+    ev.consume_fuel(call.offset, count)?;
+    Ok(Value::Str(text.repeat(count)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{Limits, FUEL_EXHAUSTED, OUTPUT_LIMIT_REACHED};
+    use crate::tokenize_to_vec;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        Environment::standard().eval(&tokens)
+    }
+
+    #[test]
+    fn concat() {
+        assert_eq!(
+            eval(b"{concat}{concat,a,,b{concat,c}}"),
+            Ok(b"abc".to_vec())
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(eval(b"{repeat,ab,3}{repeat,ab,0}"), Ok(b"ababab".to_vec()));
+        assert_eq!(eval(b"{repeat,ab,x}"), Err((11, "expected an index")));
+        let input = b"{repeat,ab,99999999999}";
+        assert_eq!(eval(input), Err((0, OUTPUT_LIMIT_REACHED)));
+        let input = b"{repeat,,99999999999}";
+        assert_eq!(eval(input), Err((0, FUEL_EXHAUSTED)));
+        let input = b"{repeat,{repeat,{repeat,abcd,1000},1000},1000}";
+        assert_eq!(eval(input), Err((8, OUTPUT_LIMIT_REACHED)));
+        // checked as the args are added, not once they're all together
+        let tokens =
+            tokenize_to_vec(b"{let,big,{repeat,x,600000},{concat,{$big},{$big},{$big}}}").unwrap();
+        let mut env = Environment::standard();
+        env.set_limits(Limits {
+            fuel: usize::MAX,
+            ..env.limits()
+        });
+        assert_eq!(env.eval(&tokens), Err((27, OUTPUT_LIMIT_REACHED)));
+    }
+}
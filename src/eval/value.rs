@@ -0,0 +1,241 @@
+//! values passed between functions. nested calls hand over values as they
+//! are, and only the final output (or an arg which mixes text and calls) is
+//! turned into text
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Write};
+
+use super::math::Number;
+use super::parse_index;
+
+/// the text given for a true boolean
+pub const TRUE: &[u8] = b"true";
+/// the text given for a false boolean
+pub const FALSE: &[u8] = b"false";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(Vec<u8>),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+/// the length of what's formatted, without keeping it
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+fn display_len(v: impl Display) -> usize {
+    let mut counter = Counter(0);
+    write!(counter, "{}", v).expect("counting doesn't fail");
+    counter.0
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Str(Vec::new())
+    }
+}
+
+impl Value {
+    /// the value as text. numbers are written like `Number::render`, booleans
+    /// as `true` or `false`, and lists as their items separated by `,`
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Str(v) => out.extend_from_slice(v),
+            Value::Int(_) | Value::Float(_) => {
+                out.extend_from_slice(&self.as_number().unwrap_or(Number::Int(0)).render())
+            }
+            Value::Bool(v) => out.extend_from_slice(if *v { TRUE } else { FALSE }),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push(b',');
+                    }
+                    item.write(out);
+                }
+            }
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Value::Str(v) => v,
+            v => {
+                let mut out = Vec::new();
+                v.write(&mut out);
+                out
+            }
+        }
+    }
+
+    /// the length of the text from `write`, without writing it
+    pub fn text_len(&self) -> usize {
+        match self {
+            Value::Str(v) => v.len(),
+            Value::Int(v) => display_len(v),
+            Value::Float(v) => display_len(v),
+            Value::Bool(v) => if *v { TRUE } else { FALSE }.len(),
+            Value::List(items) => {
+                items.iter().map(Value::text_len).sum::<usize>() + items.len().saturating_sub(1)
+            }
+        }
+    }
+
+    /// false for `false`, zero, empty text, the text `false`, and an empty
+    /// list. everything else is true
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Str(v) => !v.is_empty() && v != FALSE,
+            Value::Int(v) => *v != 0,
+            Value::Float(v) => *v != 0.0,
+            Value::Bool(v) => *v,
+            Value::List(items) => !items.is_empty(),
+        }
+    }
+
+    /// numbers as they are, and text which parses as a number
+    pub(super) fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Int(v) => Some(Number::Int(*v)),
+            Value::Float(v) => Some(Number::Float(*v)),
+            Value::Str(v) => Number::parse(v),
+            Value::Bool(_) | Value::List(_) => None,
+        }
+    }
+
+    /// non negative integers, and text which parses as one
+    pub fn as_index(&self) -> Option<usize> {
+        match self {
+            Value::Int(v) => usize::try_from(*v).ok(),
+            Value::Str(v) => parse_index(v.trim_ascii()),
+            _ => None,
+        }
+    }
+
+    /// numbers are compared by value. anything else is compared by its text,
+    /// so `{eq,{add,1,1},2}` is true
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                self.as_number().map(Number::as_f64) == other.as_number().map(Number::as_f64)
+            }
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (a, b) => a.clone().into_bytes() == b.clone().into_bytes(),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(v: &[u8]) -> Self {
+        Value::Str(v.to_vec())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<Number> for Value {
+    fn from(v: Number) -> Self {
+        match v {
+            Number::Int(v) => Value::Int(v),
+            Number::Float(v) => Value::Float(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn text() {
+        let list = Value::List(vec![
+            Value::Str(b"a".to_vec()),
+            Value::Int(-2),
+            Value::Float(0.5),
+            Value::Bool(true),
+            Value::List(vec![Value::Int(1), Value::Int(2)]),
+            Value::List(vec![]),
+        ]);
+        assert_eq!(list.clone().into_bytes(), b"a,-2,0.5,true,1,2,");
+        assert_eq!(list.text_len(), 18);
+        for v in [
+            Value::Int(i64::MIN),
+            Value::Float(1e21),
+            Value::Float(-0.125),
+        ] {
+            assert_eq!(v.text_len(), v.clone().into_bytes().len());
+        }
+        assert_eq!(Value::Float(3.0).into_bytes(), b"3");
+    }
+
+    #[test]
+    fn truthiness() {
+        for v in [
+            Value::Str(b"".to_vec()),
+            Value::Str(b"false".to_vec()),
+            Value::Int(0),
+            Value::Float(0.0),
+            Value::Bool(false),
+            Value::List(vec![]),
+        ] {
+            assert!(!v.truthy(), "{:?}", v);
+        }
+        for v in [
+            Value::Str(b"0".to_vec()),
+            Value::Str(b" ".to_vec()),
+            Value::Int(-1),
+            Value::List(vec![Value::Bool(false)]),
+        ] {
+            assert!(v.truthy(), "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn coercion() {
+        assert_eq!(
+            Value::Str(b" 12 ".to_vec()).as_number(),
+            Some(Number::Int(12))
+        );
+        assert_eq!(Value::Bool(true).as_number(), None);
+        assert_eq!(Value::List(vec![]).as_number(), None);
+        assert_eq!(Value::Str(b"3".to_vec()).as_index(), Some(3));
+        assert_eq!(Value::Int(-3).as_index(), None);
+        assert_eq!(Value::Float(3.0).as_index(), None);
+    }
+
+    #[test]
+    fn equality() {
+        assert!(Value::Int(2).equals(&Value::Float(2.0)));
+        assert!(Value::Int(2).equals(&Value::Str(b"2".to_vec())));
+        assert!(!Value::Float(2.0).equals(&Value::Str(b"2.0".to_vec())));
+        assert!(Value::Bool(true).equals(&Value::Str(b"true".to_vec())));
+        let list = Value::List(vec![Value::Int(1), Value::Int(2)]);
+        assert!(list.equals(&Value::Str(b"1,2".to_vec())));
+    }
+}
@@ -0,0 +1,114 @@
+//! variables come from the host through a `Context`, or from `let` bindings
+//! within the expression
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use super::{Call, Environment, EvalError, Evaluation, Value};
+
+/// gives values for variables during evaluation
+pub trait Context {
+    /// `path` is the variable name split on `.`, so `{$user.name}` gives
+    /// `["user", "name"]`
+    fn lookup(&self, path: &[&[u8]]) -> Option<Value>;
+}
+
+/// no variables
+impl Context for () {
+    fn lookup(&self, _: &[&[u8]]) -> Option<Value> {
+        None
+    }
+}
+
+/// a flat map of text. nested keys are stored dotted, like `user.name`
+impl Context for BTreeMap<Vec<u8>, Vec<u8>> {
+    fn lookup(&self, path: &[&[u8]]) -> Option<Value> {
+        self.get(&path.join(&b'.')).cloned().map(Value::Str)
+    }
+}
+
+/// a flat map. nested keys are stored dotted, like `user.name`
+impl Context for BTreeMap<Vec<u8>, Value> {
+    fn lookup(&self, path: &[&[u8]]) -> Option<Value> {
+        self.get(&path.join(&b'.')).cloned()
+    }
+}
+
+pub(super) fn register(env: &mut Environment) {
+    env.register(b"var", var);
+    env.register(b"let", let_);
+}
+
+/// `{var,name}` or `{var,name,default}`. the default is only evaluated if the
+/// variable doesn't exist
+fn var(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(1, 2)?;
+    let name = ev.arg_bytes(call, 0)?;
+    match ev.lookup(call.offset, &name)? {
+        Some(v) => Ok(v),
+        None if call.num_args == 2 => ev.arg(call, 1),
+        None => Err((call.offset, "unknown variable")),
+    }
+}
+
+/// `{let,name,value,body}`. the binding is only visible within the body
+fn let_(ev: &mut Evaluation, call: &Call) -> Result<Value, EvalError> {
+    call.expect_args(3, 3)?;
+    let name = ev.arg_bytes(call, 0)?;
+    let value = ev.arg(call, 1)?;
+    ev.arg_with(call, 2, &name, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::FUEL_EXHAUSTED;
+    use crate::tokenize_to_vec;
+
+    fn eval(input: &[u8]) -> Result<Vec<u8>, EvalError> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let mut context = BTreeMap::new();
+        context.insert(b"user".to_vec(), b"bob".to_vec());
+        context.insert(b"user.name".to_vec(), b"Bob".to_vec());
+        Environment::standard().eval_with(&tokens, &context)
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(eval(b"hi {var,user.name}!"), Ok(b"hi Bob!".to_vec()));
+        assert_eq!(eval(b"{$user}{$user.name}"), Ok(b"bobBob".to_vec()));
+        assert_eq!(eval(b"{var,us{if,x,er}}"), Ok(b"bob".to_vec()));
+        assert_eq!(eval(b"a{$nope}"), Err((1, "unknown variable")));
+        assert_eq!(eval(b"{var,nope,fallback}"), Ok(b"fallback".to_vec()));
+        assert_eq!(eval(b"{$user,x}"), Err((0, "wrong number of arguments")));
+    }
+
+    #[test]
+    fn values() {
+        let tokens = tokenize_to_vec(b"{len,{$items}} {$items}").unwrap();
+        let mut context = BTreeMap::new();
+        let items = Value::List(alloc::vec![Value::Int(1), Value::Int(2)]);
+        context.insert(b"items".to_vec(), items);
+        let env = Environment::standard();
+        assert_eq!(env.eval_with(&tokens, &context), Ok(b"2 1,2".to_vec()));
+    }
+
+    #[test]
+    fn let_scoping() {
+        assert_eq!(
+            eval(b"{let,x,1,{$x}{let,x,2,{$x}}{$x}}"),
+            Ok(b"121".to_vec())
+        );
+        assert_eq!(
+            eval(b"{let,user,alice,{$user}}{$user}"),
+            Ok(b"alicebob".to_vec())
+        );
+        assert_eq!(eval(b"{let,x,1,}{$x}"), Err((10, "unknown variable")));
+    }
+
+    #[test]
+    fn copies_take_fuel() {
+        let input = b"{let,big,{repeat,x,300000},{$big}{$big}{var,big}{$big}}";
+        assert_eq!(eval(input), Err((39, FUEL_EXHAUSTED)));
+    }
+}
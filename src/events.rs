@@ -0,0 +1,139 @@
+//! scans input and calls a handler for each part of it, without making tokens.
+//! only the open functions are kept, so this suits giving output as it goes
+
+use crate::stream::{Name, NameMode, StreamToken, StreamTokenizer};
+
+/// called as the input is scanned. offsets are into the input
+#[allow(unused_variables)]
+pub trait Handler<'a> {
+    /// a run of text, which is part of the input. the `\` of escapes isn't
+    /// included, so escapes can split runs
+    fn on_text(&mut self, offset: usize, text: &'a [u8]) {}
+    /// the `{` of a function, once its name is complete
+    fn on_function_start(&mut self, offset: usize, name: &'a [u8]) {}
+    /// a `,` before each argument, including the first
+    fn on_arg_separator(&mut self, offset: usize) {}
+    /// the `}` of a function
+    fn on_function_end(&mut self, offset: usize) {}
+}
+
+/// calls the handler for each part of the input. on err, gives the same as
+/// `tokenize`, but the handler has been called for the input before it
+pub fn scan<'a>(
+    input: &'a [u8],
+    handler: &mut impl Handler<'a>,
+) -> Result<(), (usize, &'static str)> {
+    let mut tokenizer = StreamTokenizer::new(NameMode::Span);
+    // the text which hasn't been given yet
+    let mut run = 0..0;
+    tokenizer.push(input, &mut |token| {
+        if let StreamToken::Character(character) = token {
+            // an escaped character is after its `\`
+            let offset = if input[character.offset] == character.val {
+                character.offset
+            } else {
+                character.offset + 1
+            };
+            if offset != run.end {
+                if !run.is_empty() {
+                    handler.on_text(run.start, &input[run.clone()]);
+                }
+                run = offset..offset;
+            }
+            run.end += 1;
+            return;
+        }
+        if !run.is_empty() {
+            handler.on_text(run.start, &input[run.clone()]);
+            run = 0..0;
+        }
+        match token {
+            StreamToken::Function {
+                offset,
+                name: Name::Span(name),
+            } => {
+                handler.on_function_start(offset, &input[name.clone()]);
+                if input[name.end] == b',' {
+                    handler.on_arg_separator(name.end);
+                }
+            }
+            StreamToken::FunctionArgEnd { offset } if input[offset] == b',' => {
+                handler.on_arg_separator(offset)
+            }
+            StreamToken::FunctionEnd { offset, .. } => handler.on_function_end(offset),
+            _ => (),
+        }
+    });
+    if !run.is_empty() {
+        handler.on_text(run.start, &input[run]);
+    }
+    tokenizer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    /// writes each call, like `f(|a|b)` for `{f,a,b}`
+    #[derive(Default)]
+    struct Outline {
+        out: String,
+        offsets: Vec<usize>,
+    }
+
+    impl<'a> Handler<'a> for Outline {
+        fn on_text(&mut self, offset: usize, text: &'a [u8]) {
+            self.out.push('[');
+            self.out.push_str(core::str::from_utf8(text).unwrap());
+            self.out.push(']');
+            self.offsets.push(offset);
+        }
+
+        fn on_function_start(&mut self, offset: usize, name: &'a [u8]) {
+            self.out.push_str(core::str::from_utf8(name).unwrap());
+            self.out.push('(');
+            self.offsets.push(offset);
+        }
+
+        fn on_arg_separator(&mut self, offset: usize) {
+            self.out.push('|');
+            self.offsets.push(offset);
+        }
+
+        fn on_function_end(&mut self, offset: usize) {
+            self.out.push(')');
+            self.offsets.push(offset);
+        }
+    }
+
+    fn outline(input: &[u8]) -> (Result<(), (usize, &'static str)>, Outline) {
+        let mut handler = Outline::default();
+        (scan(input, &mut handler), handler)
+    }
+
+    #[test]
+    fn calls() {
+        let (result, handler) = outline(b"ab{f,x,{g}}{h,}c");
+        assert_eq!(result, Ok(()));
+        assert_eq!(handler.out, "[ab]f(|[x]|g())h(|)[c]");
+        assert_eq!(handler.offsets, [0, 2, 4, 5, 6, 7, 9, 10, 11, 13, 14, 15]);
+    }
+
+    #[test]
+    fn escapes() {
+        let (_, handler) = outline(b"a\\{b\\\\c\\nd,}");
+        assert_eq!(handler.out, "[a][{b\\][c\\nd,}]");
+        assert_eq!(handler.offsets, [0, 2, 6]);
+        assert_eq!(outline(b"x\\").1.out, "[x]");
+    }
+
+    #[test]
+    fn errors() {
+        let (result, handler) = outline(b"a{f,b{g");
+        assert_eq!(result, Err((6, "function name wasn't completed")));
+        assert_eq!(handler.out, "[a]f(|[b]");
+        assert_eq!(outline(b"{f,a").0, Err((0, "unclosed function")));
+    }
+}
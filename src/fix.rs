@@ -0,0 +1,268 @@
+//! collects fixes for lints and common mistakes, so they can be applied
+//! together
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::edit::{self, Edit, Fix};
+use crate::lint::lint;
+use crate::schema::{check, Schema};
+use crate::{tokenize_to_vec, unclosed, Function, Token};
+
+/// the fixes for input, which might not tokenize:
+///
+/// - an unfinished function name is escaped, with its `{`
+/// - unclosed functions are closed at the end of the input
+/// - a trailing escape is escaped
+/// - the fixes from `lint`
+/// - unknown functions are renamed to a function from the schema, if one is
+///   a close enough match
+///
+/// the fixes don't overlap
+pub fn fixes(input: &[u8], schema: &Schema) -> Vec<Fix> {
+    let mut fixes = Vec::new();
+    let state = unclosed(input);
+    if state.escape {
+        fixes.push(Fix {
+            message: "escape the trailing `\\`",
+            edit: Edit::insert(input.len(), b"\\"),
+            safe: false,
+        });
+    }
+    // everything after an unfinished name is part of the name, so nothing
+    // there is looked at
+    let mut valid_up_to = input.len();
+    if let Some(name) = state.name {
+        valid_up_to = name;
+        // a name can't have `,` or `}`, or it would have ended
+        let mut escaped = Vec::new();
+        for ch in &input[name..] {
+            if matches!(ch, b'{' | b'\\') {
+                escaped.push(b'\\');
+            }
+            escaped.push(*ch);
+        }
+        fixes.push(Fix {
+            message: "escape the `{` and the name",
+            edit: Edit::replace(name..input.len(), &escaped),
+            safe: false,
+        });
+    }
+    if !state.functions.is_empty() {
+        fixes.push(Fix {
+            message: "close the unclosed functions",
+            edit: Edit::insert(input.len(), &vec![b'}'; state.functions.len()]),
+            safe: false,
+        });
+    }
+
+    // tokens before valid_up_to are the same with the above fixes applied
+    let edits: Vec<Edit> = fixes.iter().map(|f| f.edit.clone()).collect();
+    let patched = match edit::apply(input, &edits) {
+        Ok(v) => v,
+        Err(_) => return fixes,
+    };
+    let tokens = match tokenize_to_vec(&patched) {
+        Ok(v) => v,
+        Err(_) => return fixes,
+    };
+
+    for found in lint(&patched, &tokens) {
+        if let Some(fix) = found.fix {
+            if fix.edit.range.end <= valid_up_to {
+                fixes.push(fix);
+            }
+        }
+    }
+
+    for violation in check(&tokens, schema) {
+        if violation.reason != "unknown function" || violation.offset >= valid_up_to {
+            continue;
+        }
+        let function = match tokens[violation.index] {
+            Token::Function(v) => v,
+            _ => continue,
+        };
+        if let Some(name) = closest_name(function.name, schema) {
+            fixes.push(Fix {
+                message: "rename to a known function",
+                edit: Edit::replace(name_range(&function), name),
+                safe: false,
+            });
+        }
+    }
+
+    fixes.sort_by_key(|fix| fix.edit.range.start);
+    fixes
+}
+
+/// applies the fixes for input, or only the safe ones
+pub fn fix(input: &[u8], schema: &Schema, safe_only: bool) -> Vec<u8> {
+    let edits: Vec<Edit> = fixes(input, schema)
+        .into_iter()
+        .filter(|fix| fix.safe || !safe_only)
+        .map(|fix| fix.edit)
+        .collect();
+    edit::apply(input, &edits).expect("fixes don't overlap")
+}
+
+/// applies the safe fixes for input, which must tokenize. the result has the
+/// same tokens, apart from offsets
+pub fn format(input: &[u8]) -> Result<Vec<u8>, (usize, &'static str)> {
+    let tokens = tokenize_to_vec(input)?;
+    let edits: Vec<Edit> = lint(input, &tokens)
+        .into_iter()
+        .filter_map(|found| found.fix)
+        .filter(|fix| fix.safe)
+        .map(|fix| fix.edit)
+        .collect();
+    Ok(edit::apply(input, &edits).expect("fixes don't overlap"))
+}
+
+fn name_range(function: &Function) -> Range<usize> {
+    let begin = function.offset + 1;
+    begin..begin + function.name.len()
+}
+
+/// the only name in the schema which is within a small edit distance of name
+fn closest_name<'s>(name: &[u8], schema: &Schema<'s>) -> Option<&'s [u8]> {
+    if name.is_empty() {
+        return None;
+    }
+    let limit = (name.len() / 3).max(1);
+    let mut best: Option<(usize, &'s [u8])> = None;
+    let mut tied = false;
+    for signature in schema.signatures() {
+        let distance = edit_distance(name, signature.name);
+        if distance > limit {
+            continue;
+        }
+        match best {
+            Some((d, _)) if d < distance => (),
+            Some((d, _)) if d == distance => tied = true,
+            _ => {
+                best = Some((distance, signature.name));
+                tied = false;
+            }
+        }
+    }
+    match (best, tied) {
+        (Some((_, name)), false) => Some(name),
+        _ => None,
+    }
+}
+
+/// levenshtein distance
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(input: &[u8]) -> Vec<u8> {
+        fix(input, &Schema::standard(), false)
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance(b"kitten", b"sitting"), 3);
+        assert_eq!(edit_distance(b"", b"abc"), 3);
+        assert_eq!(edit_distance(b"plural", b"plural"), 0);
+        assert_eq!(edit_distance(b"fi", b"if"), 2);
+    }
+
+    #[test]
+    fn structure() {
+        assert_eq!(fixed(b"{if,a,{not,b"), b"{if,a,{not,b}}");
+        assert_eq!(fixed(b"a\\"), b"a\\\\");
+        assert_eq!(fixed(b"{if,a\\"), b"{if,a\\\\}");
+        assert_eq!(fixed(b"{if,a,b} {x"), b"{if,a,b} \\{x");
+        assert_eq!(fixed(b"{if,a,{b"), b"{if,a,\\{b}");
+        assert_eq!(fixed(b"{if,a,{b\\"), b"{if,a,\\{b\\\\}");
+        assert_eq!(fixed(b"{a{b"), b"\\{a\\{b");
+        assert_eq!(fixed(b"{a,{b{c"), b"{a,\\{b\\{c}");
+    }
+
+    #[test]
+    fn always_tokenizes() {
+        // every input up to 6 bytes long from these
+        let alphabet = b"{},\\a";
+        let mut inputs: Vec<Vec<u8>> = vec![Vec::new()];
+        let mut last = inputs.clone();
+        for _ in 0..6 {
+            last = last
+                .iter()
+                .flat_map(|input| {
+                    alphabet.iter().map(move |ch| {
+                        let mut next = input.clone();
+                        next.push(*ch);
+                        next
+                    })
+                })
+                .collect();
+            inputs.extend(last.iter().cloned());
+        }
+        for input in inputs {
+            let output = fixed(&input);
+            assert!(
+                tokenize_to_vec(&output).is_ok(),
+                "{:?} -> {:?}",
+                input.escape_ascii().to_string(),
+                output.escape_ascii().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn renames() {
+        assert_eq!(
+            fixed(b"{plurl,1,one,a,other,b}"),
+            b"{plural,1,one,a,other,b}"
+        );
+        assert_eq!(
+            fixed(b"{concat,{lenn,abc},{repaet,x,2}}"),
+            b"{concat,{len,abc},{repeat,x,2}}"
+        );
+        // too far, and ambiguous between abs, add and and
+        assert_eq!(fixed(b"{xyzzy}{ad,1}"), b"{xyzzy}{ad,1}");
+    }
+
+    #[test]
+    fn safe_only() {
+        let input = b"\\,{concat,\\n,} {plurl,1";
+        let all = fixes(input, &Schema::standard());
+        let found: Vec<_> = all.iter().map(|f| (f.edit.range.start, f.safe)).collect();
+        assert_eq!(
+            found,
+            [(0, true), (10, true), (12, false), (16, false), (23, false)]
+        );
+        assert_eq!(
+            fix(input, &Schema::standard(), true),
+            b",{concat,\\\\n,} {plurl,1"
+        );
+        assert_eq!(fixed(input), b",{concat,\\\\n} {plural,1}");
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format(b"a\\}{if,\\q,}"), Ok(b"a}{if,\\\\q,}".to_vec()));
+        assert_eq!(format(b"{a,"), Err((0, "unclosed function")));
+    }
+}
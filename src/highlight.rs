@@ -0,0 +1,167 @@
+//! classifies the bytes of an input for syntax highlighting
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{recover, tokenize_to_vec, unclosed, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    FunctionName,
+    OpenBrace,
+    /// a `,` between a function's name and args, or between args
+    Separator,
+    CloseBrace,
+    /// an escape and the character it escapes, like `\{`
+    Escape,
+    Text,
+    /// the `{` of a function which isn't closed, or a trailing `\`
+    Error,
+}
+
+impl Class {
+    /// a name for the class, like `function-name`
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::FunctionName => "function-name",
+            Class::OpenBrace => "open-brace",
+            Class::Separator => "separator",
+            Class::CloseBrace => "close-brace",
+            Class::Escape => "escape",
+            Class::Text => "text",
+            Class::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub class: Class,
+}
+
+/// spans which cover the whole input, in order. neighbouring bytes of the same
+/// class are in the same span. this works on input which doesn't tokenize, as
+/// if whatever was open at the end had been closed
+pub fn classify(input: &[u8]) -> Vec<Span> {
+    let mut classes = vec![Class::Text; input.len()];
+    let mut set = |range: Range<usize>, class: Class| {
+        let end = range.end.min(input.len());
+        if range.start < end {
+            classes[range.start..end].fill(class);
+        }
+    };
+
+    let recovered = recover(input);
+    let tokens = tokenize_to_vec(&recovered).unwrap_or_default();
+    for token in &tokens {
+        match token {
+            Token::Character(character) => {
+                // a `\` before something which can't be escaped is text
+                let escaped =
+                    character.val != b'\\' || recovered.get(character.offset + 1) == Some(&b'\\');
+                if recovered[character.offset] == b'\\' && escaped {
+                    set(character.offset..character.offset + 2, Class::Escape);
+                }
+            }
+            Token::Function(function) => {
+                let name_end = function.offset + 1 + function.name.len();
+                set(function.offset..function.offset + 1, Class::OpenBrace);
+                set(function.offset + 1..name_end, Class::FunctionName);
+                let after = match function.first_arg_delta {
+                    Some(_) => Class::Separator,
+                    None => Class::CloseBrace,
+                };
+                set(name_end..name_end + 1, after);
+            }
+            Token::FunctionArgEnd(arg_end) => {
+                let class = match arg_end.arg_delta {
+                    Some(_) => Class::Separator,
+                    None => Class::CloseBrace,
+                };
+                set(arg_end.offset..arg_end.offset + 1, class);
+            }
+            Token::Invalid => (),
+        }
+    }
+
+    let state = unclosed(input);
+    for offset in state.functions.iter().chain(state.name.iter()) {
+        set(*offset..*offset + 1, Class::Error);
+    }
+    if state.escape {
+        set(input.len() - 1..input.len(), Class::Error);
+    }
+
+    let mut spans: Vec<Span> = Vec::new();
+    for (i, class) in classes.into_iter().enumerate() {
+        match spans.last_mut() {
+            Some(span) if span.class == class => span.range.end = i + 1,
+            _ => spans.push(Span {
+                range: i..i + 1,
+                class,
+            }),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a letter per byte
+    fn letters(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for span in classify(input) {
+            let letter = match span.class {
+                Class::FunctionName => b'n',
+                Class::OpenBrace => b'{',
+                Class::Separator => b',',
+                Class::CloseBrace => b'}',
+                Class::Escape => b'\\',
+                Class::Text => b't',
+                Class::Error => b'!',
+            };
+            out.resize(out.len() + span.range.len(), letter);
+        }
+        out
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(letters(b"ab {f,x\\,,{}} {g}"), b"ttt{n,t\\\\,{}}t{n}");
+        assert_eq!(letters(b"\\{\\\\\\n}"), b"\\\\\\\\ttt");
+        assert_eq!(
+            classify(b"x{ab}"),
+            [
+                Span {
+                    range: 0..1,
+                    class: Class::Text
+                },
+                Span {
+                    range: 1..2,
+                    class: Class::OpenBrace
+                },
+                Span {
+                    range: 2..4,
+                    class: Class::FunctionName
+                },
+                Span {
+                    range: 4..5,
+                    class: Class::CloseBrace
+                },
+            ]
+        );
+        assert!(classify(b"").is_empty());
+    }
+
+    #[test]
+    fn recovery() {
+        assert_eq!(letters(b"{a,{b,c"), b"!n,!n,t");
+        assert_eq!(letters(b"{a,x{bc"), b"!n,t!nn");
+        assert_eq!(letters(b"{a,x\\"), b"!n,t!");
+        assert_eq!(letters(b"a}b,"), b"tttt");
+    }
+}
@@ -0,0 +1,220 @@
+//! updates tokens after an edit, without tokenizing the whole input again
+
+use alloc::vec::Vec;
+
+use crate::edit::Edit;
+use crate::{tokenize_to_vec, Function, Token};
+
+/// the offset of a function's `}`
+pub(crate) fn close_offset(tokens: &[Token], index: usize, function: &Function) -> usize {
+    match function.first_arg_delta {
+        // the END_ARG of the last arg
+        Some(_) => tokens[index + function.delta - 1].offset(),
+        None => function.offset + 1 + function.name.len(),
+    }
+}
+
+/// the indices of the functions which contain the edited range, outermost
+/// first. the range can't include a function's `{` or `}`
+fn enclosing(tokens: &[Token], range: &core::ops::Range<usize>) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let function = match &tokens[i] {
+            Token::Function(v) => v,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if function.offset >= range.end {
+            break;
+        }
+        if function.offset < range.start && range.end <= close_offset(tokens, i, function) {
+            found.push(i);
+            i += 1;
+        } else {
+            i += function.delta;
+        }
+    }
+    found
+}
+
+/// gives the tokens for new_input, which is input with the edit applied.
+/// tokens must be from a successful `tokenize` of input.
+///
+/// only the smallest function around the edit is tokenized again, and the
+/// rest of the tokens are moved and have their names point into new_input. if
+/// the edit changes where that function ends, a larger one is tried, and
+/// finally the whole input. on err, gives the same as `tokenize` of new_input
+pub fn retokenize<'a>(
+    tokens: &[Token],
+    input: &[u8],
+    edit: &Edit,
+    new_input: &'a [u8],
+) -> Result<Vec<Token<'a>>, (usize, &'static str)> {
+    debug_assert!(edit.range.end <= input.len());
+    debug_assert!(new_input.len() + edit.range.len() == input.len() + edit.replacement.len());
+    let shift = |offset: usize| offset + edit.replacement.len() - edit.range.len();
+
+    for index in enclosing(tokens, &edit.range).into_iter().rev() {
+        let function = match tokens[index] {
+            Token::Function(v) => v,
+            _ => unreachable!(),
+        };
+        let open = function.offset;
+        let close = shift(close_offset(tokens, index, &function));
+        let sub = match tokenize_to_vec(&new_input[open..=close]) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        // the text must still be exactly one function
+        let whole = match sub.first() {
+            Some(Token::Function(f)) => {
+                f.delta == sub.len() && close_offset(&sub, 0, f) == close - open
+            }
+            _ => false,
+        };
+        if !whole {
+            continue;
+        }
+
+        let old_len = function.delta;
+        let mut output = Vec::with_capacity(tokens.len() - old_len + sub.len());
+        for (j, token) in tokens[..index].iter().enumerate() {
+            // links which jump over the function move with the tokens after it
+            let fix = |delta: usize| {
+                if j + delta >= index + old_len {
+                    delta + sub.len() - old_len
+                } else {
+                    delta
+                }
+            };
+            output.push(match *token {
+                Token::Function(f) => Token::Function(Function {
+                    name: &new_input[f.offset + 1..f.offset + 1 + f.name.len()],
+                    delta: fix(f.delta),
+                    first_arg_delta: f.first_arg_delta.map(fix),
+                    ..f
+                }),
+                Token::FunctionArgEnd(mut arg_end) => {
+                    arg_end.arg_delta = arg_end.arg_delta.map(fix);
+                    Token::FunctionArgEnd(arg_end)
+                }
+                Token::Character(c) => Token::Character(c),
+                Token::Invalid => Token::Invalid,
+            });
+        }
+        for token in sub {
+            output.push(match token {
+                Token::Function(mut f) => {
+                    f.offset += open;
+                    Token::Function(f)
+                }
+                Token::FunctionArgEnd(mut arg_end) => {
+                    arg_end.offset += open;
+                    Token::FunctionArgEnd(arg_end)
+                }
+                Token::Character(mut c) => {
+                    c.offset += open;
+                    Token::Character(c)
+                }
+                Token::Invalid => Token::Invalid,
+            });
+        }
+        for token in &tokens[index + old_len..] {
+            output.push(match *token {
+                Token::Function(f) => {
+                    let offset = shift(f.offset);
+                    Token::Function(Function {
+                        offset,
+                        name: &new_input[offset + 1..offset + 1 + f.name.len()],
+                        ..f
+                    })
+                }
+                Token::FunctionArgEnd(mut arg_end) => {
+                    arg_end.offset = shift(arg_end.offset);
+                    Token::FunctionArgEnd(arg_end)
+                }
+                Token::Character(mut c) => {
+                    c.offset = shift(c.offset);
+                    Token::Character(c)
+                }
+                Token::Invalid => Token::Invalid,
+            });
+        }
+        return Ok(output);
+    }
+    tokenize_to_vec(new_input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::apply;
+
+    #[test]
+    fn smallest_function() {
+        let input = b"a{f,{g,xy},{h}}{i,z}";
+        let tokens = tokenize_to_vec(input).unwrap();
+        assert_eq!(enclosing(&tokens, &(8..9)), [1, 2]);
+        assert_eq!(enclosing(&tokens, &(7..7)), [1, 2]);
+        // the `}` of g
+        assert_eq!(enclosing(&tokens, &(9..10)), [1]);
+        assert_eq!(enclosing(&tokens, &(18..19)), [9]);
+        assert_eq!(enclosing(&tokens, &(14..16)), []);
+        assert_eq!(enclosing(&tokens, &(0..1)), []);
+    }
+
+    fn check(input: &[u8], edit: Edit) {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let new_input = apply(input, core::slice::from_ref(&edit)).unwrap();
+        assert_eq!(
+            retokenize(&tokens, input, &edit, &new_input),
+            tokenize_to_vec(&new_input),
+            "{:?} {:?}",
+            core::str::from_utf8(input),
+            edit
+        );
+    }
+
+    #[test]
+    fn edits() {
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::replace(8..9, b"{k,1,2}"));
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::delete(5..6));
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::insert(9, b"}"));
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::insert(9, b","));
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::delete(9..10));
+        check(b"a{f,{g,xy},{h}}{i,z}", Edit::insert(0, b"\\"));
+        check(b"{f,a\\\\b}", Edit::delete(4..5));
+        check(b"{f,a}", Edit::insert(4, b"{"));
+    }
+
+    /// compares against a full tokenize for random inputs and edits
+    #[test]
+    fn differential() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+        const ALPHABET: &[u8] = b"{{{}}},,\\ab";
+        let mut checked = 0;
+        while checked < 2000 {
+            let len = next(24);
+            let input: Vec<u8> = (0..len).map(|_| ALPHABET[next(ALPHABET.len())]).collect();
+            if tokenize_to_vec(&input).is_err() {
+                continue;
+            }
+            let start = next(input.len() + 1);
+            let end = start + next(input.len() - start + 1).min(3);
+            let replacement: Vec<u8> = (0..next(4))
+                .map(|_| ALPHABET[next(ALPHABET.len())])
+                .collect();
+            check(&input, Edit::replace(start..end, &replacement));
+            checked += 1;
+        }
+    }
+}
@@ -1,5 +1,26 @@
 // #![no_std]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub mod cursor;
+pub mod diff;
+pub mod edit;
+pub mod eval;
+pub mod events;
+pub mod fix;
+pub mod highlight;
+pub mod incremental;
+pub mod lint;
+pub mod query;
+pub mod refactor;
+pub mod rewrite;
+pub mod schema;
+pub mod stream;
+pub mod visit;
+
 /// indicates the position in the input string in which something occurred
 type InputOffset = usize;
 
@@ -66,6 +87,18 @@ impl<'a> Default for Token<'a> {
     }
 }
 
+impl<'a> Token<'a> {
+    /// where in the input this token came from
+    pub fn offset(&self) -> InputOffset {
+        match self {
+            Token::Invalid => 0,
+            Token::Character(character) => character.offset,
+            Token::Function(function) => function.offset,
+            Token::FunctionArgEnd(function_arg_end) => function_arg_end.offset,
+        }
+    }
+}
+
 /// this function should be called in two passes. for the first pass, give None  
 /// as the output arg, and the return value is the size of the output for the  
 /// second pass. on err, gives the offending location and error reason.  
@@ -294,6 +327,110 @@ pub fn tokenize<'a>(
     Ok(output_index)
 }
 
+/// does both passes of `tokenize`, allocating the stack and output
+pub fn tokenize_to_vec(input: &[u8]) -> Result<Vec<Token<'_>>, (usize, &'static str)> {
+    let mut stack = alloc::vec![0usize; input.len()];
+    let size = tokenize(input, &mut stack, &mut None)?;
+    let mut output = alloc::vec![Token::default(); size];
+    tokenize(input, &mut stack, &mut Some(&mut output))?;
+    Ok(output)
+}
+
+/// what's still open at the end of an input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unclosed {
+    /// the offset of each function that wasn't closed, outermost first
+    pub functions: Vec<usize>,
+    /// the offset of a function whose name wasn't completed
+    pub name: Option<usize>,
+    /// the input ends with an escape, which is ignored
+    pub escape: bool,
+}
+
+/// scans input the same way as `tokenize`, but only keeps track of what's open.
+/// this works on input which doesn't tokenize
+pub fn unclosed(input: &[u8]) -> Unclosed {
+    let mut state = Unclosed::default();
+    for (i, ch) in input.iter().enumerate() {
+        if let Some(name) = state.name {
+            match *ch {
+                b',' => {
+                    state.functions.push(name);
+                    state.name = None;
+                }
+                b'}' => state.name = None,
+                _ => (),
+            }
+            continue;
+        }
+        if state.escape {
+            state.escape = false;
+            continue;
+        }
+        match *ch {
+            b'\\' => state.escape = true,
+            b'{' => state.name = Some(i),
+            b'}' => {
+                state.functions.pop();
+            }
+            _ => (),
+        }
+    }
+    state
+}
+
+/// input with whatever is open at its end closed, so that it tokenizes. the
+/// input is only added to, so its tokens keep their offsets. an unfinished
+/// name becomes a function with no args
+pub fn recover(input: &[u8]) -> Vec<u8> {
+    let state = unclosed(input);
+    let mut output = input.to_vec();
+    if state.escape {
+        output.push(b'\\');
+    }
+    if state.name.is_some() {
+        output.push(b'}');
+    }
+    output.resize(output.len() + state.functions.len(), b'}');
+    output
+}
+
+/// iterates over the arguments of a function by following the argument deltas.  
+/// each item is the range of tokens in the argument, not including its END_ARG
+pub struct FunctionArgs<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    begin: usize,
+    end: Option<usize>,
+}
+
+impl<'t, 'a> Iterator for FunctionArgs<'t, 'a> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        let end = self.end?;
+        let range = self.begin..end;
+        self.begin = end + 1;
+        self.end = match self.tokens[end] {
+            Token::FunctionArgEnd(function_arg_end) => function_arg_end.arg_delta.map(|d| end + d),
+            _ => None,
+        };
+        Some(range)
+    }
+}
+
+/// the arguments of the function at `index`. empty if that token isn't a function
+pub fn function_args<'t, 'a>(tokens: &'t [Token<'a>], index: usize) -> FunctionArgs<'t, 'a> {
+    let end = match tokens.get(index) {
+        Some(Token::Function(function)) => function.first_arg_delta.map(|d| index + d),
+        _ => None,
+    };
+    FunctionArgs {
+        tokens,
+        begin: index + 1,
+        end,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -734,4 +871,40 @@ mod tests {
         let cap = tokenize(input, &mut stack, &mut Some(&mut output));
         assert!(cap.is_err());
     }
+
+    #[test]
+    fn args_iteration() {
+        let input = b"{outer,{inner,a,b},,12}";
+        let output = tokenize_to_vec(input).unwrap();
+        let args: Vec<_> = function_args(&output, 0).collect();
+        assert_eq!(args, [1..6, 7..7, 8..10]);
+        let args: Vec<_> = function_args(&output, 1).collect();
+        assert_eq!(args, [2..3, 4..5]);
+        assert_eq!(function_args(&output, 2).count(), 0);
+    }
+
+    #[test]
+    fn unclosed_state() {
+        assert_eq!(unclosed(b"{a,{b,c}}\\{d}\\\\"), Unclosed::default());
+        let state = unclosed(b"{a,{b,c},{d,\\}e");
+        assert_eq!(state.functions, [0, 9]);
+        assert_eq!(state.name, None);
+        let state = unclosed(b"x}{a,{b,\\");
+        assert_eq!(state.functions, [2, 5]);
+        assert!(state.escape);
+        let state = unclosed(b"{a,{b\\");
+        assert_eq!((state.functions.len(), state.name, state.escape), (1, Some(3), false));
+    }
+
+    #[test]
+    fn recovery() {
+        assert_eq!(recover(b"{a,b}"), b"{a,b}");
+        assert_eq!(recover(b"{a,{b"), b"{a,{b}}");
+        assert_eq!(recover(b"{a,{b,c\\"), b"{a,{b,c\\\\}}");
+        let recovered = recover(b"x{a,y{");
+        let tokens = tokenize_to_vec(&recovered).unwrap();
+        assert_eq!(tokens[3].offset(), 5);
+        // the closing END_ARG is past the original input
+        assert_eq!(tokens.last().map(Token::offset), Some(7));
+    }
 }
@@ -0,0 +1,222 @@
+//! finds things which tokenize fine, but probably aren't what was meant
+
+use alloc::vec::Vec;
+
+use crate::edit::{Edit, Fix};
+use crate::Token;
+
+/// a `\,` or `\}` outside of a function, where it isn't needed
+pub const REDUNDANT_ESCAPE: &str = "redundant-escape";
+/// a `\` before something which can't be escaped, like `\n`, which gives both
+/// characters
+pub const LITERAL_BACKSLASH: &str = "literal-backslash";
+/// `{}` or `{,arg}`
+pub const EMPTY_FUNCTION_NAME: &str = "empty-function-name";
+/// `{f,}` or `{f,,b}`
+pub const EMPTY_ARGUMENT: &str = "empty-argument";
+/// functions nested deeper than `Options::max_depth`
+pub const EXCESSIVE_NESTING: &str = "excessive-nesting";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Hint,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// one of the constants in this module
+    pub id: &'static str,
+    pub severity: Severity,
+    /// where in the input
+    pub offset: usize,
+    pub message: &'static str,
+    pub fix: Option<Fix>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// the most functions that can be inside one another. deeper functions
+    /// are flagged, once per outermost function that's too deep
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { max_depth: 8 }
+    }
+}
+
+/// `lint_with` the default options
+pub fn lint(input: &[u8], tokens: &[Token]) -> Vec<Lint> {
+    lint_with(input, tokens, &Options::default())
+}
+
+/// checks the tokens from a successful `tokenize` of input
+pub fn lint_with(input: &[u8], tokens: &[Token], options: &Options) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    // the index one past each function the current token is in
+    let mut ends: Vec<usize> = Vec::new();
+    // the offset of the separator before the current arg, for each function
+    let mut separators: Vec<usize> = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        while ends.last().is_some_and(|end| *end <= i) {
+            ends.pop();
+            separators.pop();
+        }
+
+        match token {
+            Token::Character(character) => {
+                if input.get(character.offset) != Some(&b'\\') {
+                    continue;
+                }
+                let escaped_backslash = input.get(character.offset + 1) == Some(&b'\\');
+                if character.val == b'\\' && !escaped_backslash {
+                    lints.push(Lint {
+                        id: LITERAL_BACKSLASH,
+                        severity: Severity::Warning,
+                        offset: character.offset,
+                        message: "only `\\`, `{`, `,` and `}` can be escaped",
+                        fix: Some(Fix {
+                            message: "escape the `\\`",
+                            edit: Edit::insert(character.offset, b"\\"),
+                            safe: true,
+                        }),
+                    });
+                } else if ends.is_empty() && matches!(character.val, b',' | b'}') {
+                    lints.push(Lint {
+                        id: REDUNDANT_ESCAPE,
+                        severity: Severity::Hint,
+                        offset: character.offset,
+                        message: "escape isn't needed outside of a function",
+                        fix: Some(Fix {
+                            message: "remove the `\\`",
+                            edit: Edit::delete(character.offset..character.offset + 1),
+                            safe: true,
+                        }),
+                    });
+                }
+            }
+            Token::Function(function) => {
+                if function.name.is_empty() {
+                    lints.push(Lint {
+                        id: EMPTY_FUNCTION_NAME,
+                        severity: Severity::Warning,
+                        offset: function.offset,
+                        message: "function has no name",
+                        // `{}` was probably meant as text
+                        fix: (function.num_args == 0).then(|| Fix {
+                            message: "escape the `{`",
+                            edit: Edit::insert(function.offset, b"\\"),
+                            safe: false,
+                        }),
+                    });
+                }
+                // only the outermost functions which are too deep
+                if ends.len() == options.max_depth {
+                    lints.push(Lint {
+                        id: EXCESSIVE_NESTING,
+                        severity: Severity::Warning,
+                        offset: function.offset,
+                        message: "functions are nested too deeply",
+                        fix: None,
+                    });
+                }
+                if function.first_arg_delta.is_some() {
+                    ends.push(i + function.delta);
+                    separators.push(function.offset + 1 + function.name.len());
+                }
+            }
+            Token::FunctionArgEnd(arg_end) => {
+                let separator = separators.last_mut().expect("args are within a function");
+                if *separator + 1 == arg_end.offset {
+                    lints.push(Lint {
+                        id: EMPTY_ARGUMENT,
+                        severity: Severity::Hint,
+                        offset: arg_end.offset,
+                        message: "argument is empty",
+                        fix: Some(Fix {
+                            message: "remove the `,`",
+                            edit: Edit::delete(*separator..*separator + 1),
+                            safe: false,
+                        }),
+                    });
+                }
+                *separator = arg_end.offset;
+            }
+            Token::Invalid => (),
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn lint_ids(input: &[u8]) -> Vec<(&'static str, usize)> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        lint(input, &tokens)
+            .iter()
+            .map(|l| (l.id, l.offset))
+            .collect()
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            lint_ids(b"a\\,b\\}{f,\\,\\}}\\{\\\\"),
+            [(REDUNDANT_ESCAPE, 1), (REDUNDANT_ESCAPE, 4)]
+        );
+        assert_eq!(
+            lint_ids(b"\\n{f,\\t}\\\\n"),
+            [(LITERAL_BACKSLASH, 0), (LITERAL_BACKSLASH, 5)]
+        );
+        let input = b"x\\,";
+        let lints = lint(input, &tokenize_to_vec(input).unwrap());
+        assert_eq!(
+            lints[0].fix,
+            Some(Fix {
+                message: "remove the `\\`",
+                edit: Edit::delete(1..2),
+                safe: true
+            })
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            lint_ids(b"{}{,a}{f,}{g,,b,}{h,c}"),
+            [
+                (EMPTY_FUNCTION_NAME, 0),
+                (EMPTY_FUNCTION_NAME, 2),
+                (EMPTY_ARGUMENT, 9),
+                (EMPTY_ARGUMENT, 13),
+                (EMPTY_ARGUMENT, 16),
+            ]
+        );
+        let input = b"{}";
+        let lints = lint(input, &tokenize_to_vec(input).unwrap());
+        assert_eq!(lints[0].fix.as_ref().unwrap().edit, Edit::insert(0, b"\\"));
+    }
+
+    #[test]
+    fn nesting() {
+        let input = b"{a,{b,{c,{d}},{e}}}{f,{g,{h}}}";
+        let tokens = tokenize_to_vec(input).unwrap();
+        let lints = lint_with(input, &tokens, &Options { max_depth: 2 });
+        let found: Vec<_> = lints.iter().map(|l| (l.id, l.offset)).collect();
+        assert_eq!(
+            found,
+            [
+                (EXCESSIVE_NESTING, 6),
+                (EXCESSIVE_NESTING, 14),
+                (EXCESSIVE_NESTING, 25)
+            ]
+        );
+        assert!(lint(input, &tokens).is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! finds functions by where they're nested, like `section > link[1]`.
+//!
+//! a query is steps separated by `>`, for a function directly in an argument
+//! of the one before, or `//`, for one anywhere inside it. the first step can
+//! be at any depth. a step is a function name or `*` for any function, and
+//! `[n]` after it picks argument n, counting from 0. on the last step that
+//! gives the argument's tokens, and before it the next step must be inside
+//! that argument
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{function_args, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// `>`
+    Child,
+    /// `//`
+    Descendant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// how this is nested in the step before. the first step is a descendant
+    /// of the top level
+    pub axis: Axis,
+    /// None for `*`
+    pub name: Option<Vec<u8>>,
+    pub arg: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// the function matching the last step
+    pub index: usize,
+    /// the tokens of the argument picked by the last step, not including its
+    /// END_ARG
+    pub arg: Option<Range<usize>>,
+}
+
+/// the functions around the tokens being looked at. each has two flags for
+/// each step, kept together so that entering a function doesn't allocate
+struct Open {
+    len: usize,
+    /// for each function, whether it matched each step, with the steps before
+    /// it matching the functions around it. then whether a function around it
+    /// matched each step, in the argument which it's in
+    flags: Vec<bool>,
+    /// for each function, which of its arguments the tokens are in
+    args: Vec<usize>,
+}
+
+impl Open {
+    fn matched(&self, k: usize) -> bool {
+        self.flags[self.flags.len() - 2 * self.len + k]
+    }
+
+    fn around(&self, k: usize) -> bool {
+        self.flags[self.flags.len() - self.len + k]
+    }
+
+    /// whether the innermost function matched the step, in the argument which
+    /// the tokens are in
+    fn fits(&self, steps: &[Step], k: usize) -> bool {
+        let arg = *self.args.last().expect("checked by caller");
+        self.matched(k) && steps[k].arg.is_none_or(|n| n == arg)
+    }
+}
+
+fn is_name_byte(ch: u8) -> bool {
+    !ch.is_ascii_whitespace() && !matches!(ch, b'>' | b'/' | b'[' | b']')
+}
+
+impl Query {
+    /// on err, gives the offset in the query and the reason
+    pub fn parse(query: &[u8]) -> Result<Self, (usize, &'static str)> {
+        let mut steps = Vec::new();
+        let mut i = 0;
+        let skip_space = |i: &mut usize| {
+            while query.get(*i).is_some_and(|ch| ch.is_ascii_whitespace()) {
+                *i += 1;
+            }
+        };
+        let mut axis = Axis::Descendant;
+        loop {
+            skip_space(&mut i);
+            let begin = i;
+            while query.get(i).copied().is_some_and(is_name_byte) {
+                i += 1;
+            }
+            let name = match &query[begin..i] {
+                b"" => return Err((begin, "expected a function name")),
+                b"*" => None,
+                name => Some(name.to_vec()),
+            };
+
+            let mut arg = None;
+            if query.get(i) == Some(&b'[') {
+                i += 1;
+                let begin = i;
+                while query.get(i).is_some_and(u8::is_ascii_digit) {
+                    i += 1;
+                }
+                let n = core::str::from_utf8(&query[begin..i]).expect("digits");
+                arg = Some(n.parse().map_err(|_| (begin, "expected a number"))?);
+                if query.get(i) != Some(&b']') {
+                    return Err((i, "expected ]"));
+                }
+                i += 1;
+            }
+            steps.push(Step { axis, name, arg });
+
+            skip_space(&mut i);
+            axis = match &query[i..] {
+                [] => return Ok(Self { steps }),
+                [b'>', ..] => {
+                    i += 1;
+                    Axis::Child
+                }
+                [b'/', b'/', ..] => {
+                    i += 2;
+                    Axis::Descendant
+                }
+                _ => return Err((i, "expected > or //")),
+            };
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// the functions which match, in order. this is one pass over the tokens,
+    /// carrying which steps have matched down into each argument
+    pub fn find(&self, tokens: &[Token]) -> Vec<Match> {
+        let steps = &self.steps[..];
+        let last = steps.len() - 1;
+        let mut found = Vec::new();
+        let mut open = Open {
+            len: steps.len(),
+            flags: Vec::new(),
+            args: Vec::new(),
+        };
+        let mut matched = alloc::vec![false; steps.len()];
+        let mut i = 0;
+        while i < tokens.len() {
+            let function = match &tokens[i] {
+                Token::Function(v) => v,
+                Token::FunctionArgEnd(arg_end) => {
+                    match arg_end.arg_delta {
+                        Some(_) => *open.args.last_mut().expect("tokenized") += 1,
+                        None => {
+                            open.args.pop();
+                            open.flags.truncate(open.flags.len() - 2 * steps.len());
+                        }
+                    }
+                    i += 1;
+                    continue;
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let inside = !open.args.is_empty();
+            for (k, step) in steps.iter().enumerate() {
+                matched[k] = step
+                    .name
+                    .as_deref()
+                    .is_none_or(|name| name == function.name)
+                    && (k == 0
+                        || inside
+                            && match step.axis {
+                                Axis::Child => open.fits(steps, k - 1),
+                                Axis::Descendant => open.around(k - 1) || open.fits(steps, k - 1),
+                            });
+            }
+            if matched[last] {
+                match steps[last].arg {
+                    None => found.push(Match {
+                        index: i,
+                        arg: None,
+                    }),
+                    Some(n) => {
+                        if let Some(arg) = function_args(tokens, i).nth(n) {
+                            found.push(Match {
+                                index: i,
+                                arg: Some(arg),
+                            });
+                        }
+                    }
+                }
+            }
+            // a function without args has nothing inside to match
+            if function.first_arg_delta.is_none() {
+                i += function.delta;
+                continue;
+            }
+            for k in 0..steps.len() {
+                let around = inside && (open.around(k) || open.fits(steps, k));
+                matched.push(around);
+            }
+            open.flags.extend_from_slice(&matched);
+            matched.truncate(steps.len());
+            open.args.push(0);
+            i += 1;
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+
+    fn indices(query: &str, input: &[u8]) -> Vec<usize> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let query = Query::parse(query.as_bytes()).unwrap();
+        query.find(&tokens).into_iter().map(|m| m.index).collect()
+    }
+
+    // 0  FUNCTION section
+    // 1  |   FUNCTION link
+    // 5  |   FUNCTION if
+    // 6  |   |   FUNCTION link
+    // 13 |   FUNCTION section
+    // 14 |   |   FUNCTION link
+    // 21 FUNCTION link
+    const INPUT: &[u8] = b"{section,{link,a},{if,{link,b},c},{section,{link,d,e}}}{link,f}";
+
+    #[test]
+    fn finding() {
+        assert_eq!(indices("link", INPUT), [1, 6, 14, 21]);
+        assert_eq!(indices("section > link", INPUT), [1, 14]);
+        assert_eq!(indices("section//link", INPUT), [1, 6, 14]);
+        assert_eq!(indices("section > section > link", INPUT), [14]);
+        assert_eq!(indices("*//if", INPUT), [5]);
+        assert_eq!(indices("* > * > link", INPUT), [6, 14]);
+        assert_eq!(indices("section[1] > *", INPUT), [5]);
+        assert_eq!(indices("section[0]//link", INPUT), [1, 14]);
+        assert_eq!(indices("section[0]//if", INPUT), []);
+        assert_eq!(indices("section[2] // link", INPUT), [14]);
+        assert_eq!(indices("if > if", INPUT), []);
+    }
+
+    #[test]
+    fn arguments() {
+        let tokens = tokenize_to_vec(INPUT).unwrap();
+        let query = Query::parse(b"section > link[1]").unwrap();
+        assert_eq!(
+            query.find(&tokens),
+            [Match {
+                index: 14,
+                arg: Some(17..18)
+            }]
+        );
+        let query = Query::parse(b"link[0]").unwrap();
+        let args: Vec<_> = query.find(&tokens).into_iter().map(|m| m.arg).collect();
+        assert_eq!(args, [Some(2..3), Some(7..8), Some(15..16), Some(22..23)]);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let n = 5000;
+        let mut input = b"{f,".repeat(n);
+        input.extend(b"}".repeat(n));
+        let found = indices("f // f > f // f // f", &input);
+        assert_eq!(found.len(), n - 4);
+        assert_eq!(found.first(), Some(&4));
+    }
+
+    #[test]
+    fn parsing() {
+        let query = Query::parse(b" a>*[2]//b ").unwrap();
+        assert_eq!(
+            query.steps(),
+            [
+                Step {
+                    axis: Axis::Descendant,
+                    name: Some(b"a".to_vec()),
+                    arg: None
+                },
+                Step {
+                    axis: Axis::Child,
+                    name: None,
+                    arg: Some(2)
+                },
+                Step {
+                    axis: Axis::Descendant,
+                    name: Some(b"b".to_vec()),
+                    arg: None
+                },
+            ]
+        );
+        assert_eq!(Query::parse(b""), Err((0, "expected a function name")));
+        assert_eq!(Query::parse(b"a >"), Err((3, "expected a function name")));
+        assert_eq!(Query::parse(b"a b"), Err((2, "expected > or //")));
+        assert_eq!(Query::parse(b"a/b"), Err((1, "expected > or //")));
+        assert_eq!(Query::parse(b"a[x]"), Err((2, "expected a number")));
+        assert_eq!(Query::parse(b"a[1"), Err((3, "expected ]")));
+    }
+}
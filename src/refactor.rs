@@ -0,0 +1,287 @@
+//! changes to the structure of an input, as edits of its bytes. only the text
+//! which changes is touched, so escapes and layout elsewhere are kept. tokens
+//! must be from a successful `tokenize` of the input
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::edit::Edit;
+use crate::incremental::close_offset;
+use crate::rewrite::arg_span;
+use crate::{function_args, tokenize_to_vec, unclosed, Function, Token};
+
+fn check_name(name: &[u8]) -> Result<(), (usize, &'static str)> {
+    match name.iter().position(|ch| *ch == b',' || *ch == b'}') {
+        Some(offset) => Err((offset, "name can't contain , or }")),
+        None => Ok(()),
+    }
+}
+
+/// checks that text is exactly one argument when put in a function
+fn check_arg(arg: &[u8]) -> Result<(), (usize, &'static str)> {
+    let mut wrapped = b"{f,".to_vec();
+    wrapped.extend_from_slice(arg);
+    wrapped.push(b'}');
+    let one = match tokenize_to_vec(&wrapped) {
+        Ok(tokens) => match tokens.first() {
+            Some(Token::Function(f)) => f.num_args == 1 && f.delta == tokens.len(),
+            _ => false,
+        },
+        Err(_) => false,
+    };
+    match one {
+        true => Ok(()),
+        false => Err((0, "not one argument")),
+    }
+}
+
+fn function<'t, 'a>(
+    tokens: &'t [Token<'a>],
+    index: usize,
+) -> Result<&'t Function<'a>, (usize, &'static str)> {
+    match tokens.get(index) {
+        Some(Token::Function(function)) => Ok(function),
+        _ => Err((index, "not a function")),
+    }
+}
+
+/// renames every function called from to to. on err, gives the offset in to
+pub fn rename(
+    tokens: &[Token],
+    from: &[u8],
+    to: &[u8],
+) -> Result<Vec<Edit>, (usize, &'static str)> {
+    check_name(to)?;
+    let edits = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Function(function) if function.name == from => {
+                let begin = function.offset + 1;
+                Some(Edit::replace(begin..begin + function.name.len(), to))
+            }
+            _ => None,
+        })
+        .collect();
+    Ok(edits)
+}
+
+/// inserts arg, which is written as it would be in the input, so that it's
+/// argument n of the function at tokens\[index\]. n can be the number of args,
+/// to add it at the end. on err, gives the index or n, or the offset in arg
+pub fn insert_arg(
+    tokens: &[Token],
+    index: usize,
+    n: usize,
+    arg: &[u8],
+) -> Result<Edit, (usize, &'static str)> {
+    let function = function(tokens, index)?;
+    check_arg(arg)?;
+    if n == function.num_args {
+        let mut text = b",".to_vec();
+        text.extend_from_slice(arg);
+        return Ok(Edit::insert(close_offset(tokens, index, function), &text));
+    }
+    let range = function_args(tokens, index)
+        .nth(n)
+        .ok_or((n, "no such argument"))?;
+    let mut text = arg.to_vec();
+    text.push(b',');
+    Ok(Edit::insert(arg_span(tokens, index, &range).start, &text))
+}
+
+/// removes argument n of the function at tokens\[index\], with the separator
+/// before it. on err, gives the index or n
+pub fn remove_arg(tokens: &[Token], index: usize, n: usize) -> Result<Edit, (usize, &'static str)> {
+    function(tokens, index)?;
+    let range = function_args(tokens, index)
+        .nth(n)
+        .ok_or((n, "no such argument"))?;
+    let span = arg_span(tokens, index, &range);
+    Ok(Edit::delete(span.start - 1..span.end))
+}
+
+/// puts a range of the input into a new function, as its only argument. the
+/// range must be whole characters and functions, in the same argument or at
+/// the top level. a `,` or `}` which is text at the top level, or a `\` at the
+/// end which isn't an escape, is escaped so that it's still text in the
+/// function. on err, gives an offset in the input, or in the name
+pub fn wrap(
+    input: &[u8],
+    tokens: &[Token],
+    range: Range<usize>,
+    name: &[u8],
+) -> Result<Vec<Edit>, (usize, &'static str)> {
+    check_name(name)?;
+    let not_whole = (range.start, "range isn't whole characters and functions");
+    if range.start > range.end || range.end > input.len() {
+        return Err(not_whole);
+    }
+    // the index of the token at an offset, or the number of tokens at the end
+    let boundary = |offset: usize| match tokens.iter().position(|t| t.offset() >= offset) {
+        Some(i) if tokens[i].offset() == offset => Ok(i),
+        None if offset == input.len() && !unclosed(input).escape => Ok(tokens.len()),
+        _ => Err(not_whole),
+    };
+    let (begin, end) = (boundary(range.start)?, boundary(range.end)?);
+
+    // a `\` which isn't an escape, like in `\n`, would escape the brace put
+    // after it. an escape's token is two bytes before the next, so this is only
+    // them
+    let lone = tokens
+        .iter()
+        .any(|t| matches!(t, Token::Character(c) if c.val == b'\\' && c.offset + 1 == range.start));
+    if lone {
+        return Err((range.start, "range would change an escape"));
+    }
+
+    let mut open = b"{".to_vec();
+    open.extend_from_slice(name);
+    open.push(b',');
+    let mut edits = alloc::vec![Edit::insert(range.start, &open)];
+    let mut i = begin;
+    while i < end {
+        i += match &tokens[i] {
+            Token::Character(c) => {
+                // escapes are kept, and their token is at the `\`
+                let bare = input[c.offset] == c.val && matches!(c.val, b',' | b'}');
+                // a `\` which isn't an escape would escape the `}` after it
+                let lone = c.val == b'\\' && c.offset + 1 == range.end;
+                if bare || lone {
+                    edits.push(Edit::insert(c.offset, b"\\"));
+                }
+                1
+            }
+            Token::Function(function) => function.delta,
+            _ => return Err(not_whole),
+        };
+    }
+    if i != end {
+        return Err(not_whole);
+    }
+    edits.push(Edit::insert(range.end, b"}"));
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::apply;
+
+    fn edited(input: &[u8], edits: &[Edit]) -> Vec<u8> {
+        let output = apply(input, edits).unwrap();
+        assert!(tokenize_to_vec(&output).is_ok());
+        output
+    }
+
+    #[test]
+    fn renaming() {
+        let input = b"{old,a\\,b}{new}{old}  {oldish,{old,x}}";
+        let tokens = tokenize_to_vec(input).unwrap();
+        let edits = rename(&tokens, b"old", b"new").unwrap();
+        assert_eq!(edits.len(), 3);
+        assert_eq!(
+            edited(input, &edits),
+            b"{new,a\\,b}{new}{new}  {oldish,{new,x}}"
+        );
+        assert_eq!(
+            rename(&tokens, b"old", b"a,b"),
+            Err((1, "name can't contain , or }"))
+        );
+    }
+
+    #[test]
+    fn arguments() {
+        let input = b"{f,a, b\\,,{g}}";
+        let tokens = tokenize_to_vec(input).unwrap();
+        let insert =
+            |n: usize, arg: &[u8]| edited(input, &[insert_arg(&tokens, 0, n, arg).unwrap()]);
+        assert_eq!(insert(0, b"x"), b"{f,x,a, b\\,,{g}}");
+        assert_eq!(insert(1, b"{h,\\}}"), b"{f,a,{h,\\}}, b\\,,{g}}");
+        assert_eq!(insert(3, b""), b"{f,a, b\\,,{g},}");
+        assert_eq!(
+            insert_arg(&tokens, 0, 4, b"x"),
+            Err((4, "no such argument"))
+        );
+        assert_eq!(insert_arg(&tokens, 1, 0, b"x"), Err((1, "not a function")));
+        assert_eq!(
+            insert_arg(&tokens, 0, 0, b"x,y"),
+            Err((0, "not one argument"))
+        );
+        assert_eq!(
+            insert_arg(&tokens, 0, 0, b"x}"),
+            Err((0, "not one argument"))
+        );
+        assert_eq!(
+            insert_arg(&tokens, 0, 0, b"x\\"),
+            Err((0, "not one argument"))
+        );
+
+        let remove = |n: usize| edited(input, &[remove_arg(&tokens, 0, n).unwrap()]);
+        assert_eq!(remove(0), b"{f, b\\,,{g}}");
+        assert_eq!(remove(1), b"{f,a,{g}}");
+        assert_eq!(remove(2), b"{f,a, b\\,}");
+        assert_eq!(remove_arg(&tokens, 0, 3), Err((3, "no such argument")));
+
+        // no args to start with
+        let tokens = tokenize_to_vec(b"{f}").unwrap();
+        let edit = insert_arg(&tokens, 0, 0, b"a").unwrap();
+        assert_eq!(edited(b"{f}", &[edit]), b"{f,a}");
+    }
+
+    #[test]
+    fn wrapping() {
+        let input = b"ab\\{c{f,x,yz}\\n";
+        let tokens = tokenize_to_vec(input).unwrap();
+        let wrapped = |range: Range<usize>| {
+            wrap(input, &tokens, range, b"w").map(|edits| edited(input, &edits))
+        };
+        assert_eq!(wrapped(1..5).unwrap(), b"a{w,b\\{c}{f,x,yz}\\n");
+        assert_eq!(wrapped(5..13).unwrap(), b"ab\\{c{w,{f,x,yz}}\\n");
+        assert_eq!(wrapped(10..12).unwrap(), b"ab\\{c{f,x,{w,yz}}\\n");
+        assert_eq!(wrapped(10..10).unwrap(), b"ab\\{c{f,x,{w,}yz}\\n");
+        assert_eq!(wrapped(0..15).unwrap(), b"{w,ab\\{c{f,x,yz}\\n}");
+        let not_whole = "range isn't whole characters and functions";
+        assert_eq!(wrapped(3..5), Err((3, not_whole)));
+        assert_eq!(wrapped(4..8), Err((4, not_whole)));
+        assert_eq!(wrapped(8..11), Err((8, not_whole)));
+        assert_eq!(wrapped(13..14).unwrap(), b"ab\\{c{f,x,yz}{w,\\\\}n");
+        assert_eq!(wrapped(14..15), Err((14, "range would change an escape")));
+
+        let escaped = b"a\\\\";
+        let tokens = tokenize_to_vec(escaped).unwrap();
+        let edits = wrap(escaped, &tokens, 1..3, b"w").unwrap();
+        assert_eq!(edited(escaped, &edits), b"a{w,\\\\}");
+        let trailing = b"a\\";
+        let tokens = tokenize_to_vec(trailing).unwrap();
+        assert_eq!(wrap(trailing, &tokens, 0..2, b"w"), Err((0, not_whole)));
+    }
+
+    /// wraps the range in `w`, and checks that the text is its one argument
+    fn wraps_text(input: &[u8], range: Range<usize>, expected: &[u8], text: &[u8]) {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let output = edited(input, &wrap(input, &tokens, range, b"w").unwrap());
+        assert_eq!(output, expected);
+        let wrapped = tokenize_to_vec(&output).unwrap();
+        let begin = wrapped
+            .iter()
+            .position(|t| matches!(t, Token::Function(_)))
+            .unwrap();
+        let args: Vec<_> = function_args(&wrapped, begin).collect();
+        assert_eq!(args.len(), 1);
+        let arg: Vec<u8> = wrapped[args[0].clone()]
+            .iter()
+            .map(|token| match token {
+                Token::Character(c) => c.val,
+                token => panic!("{:?}", token),
+            })
+            .collect();
+        assert_eq!(arg, text);
+    }
+
+    #[test]
+    fn wrapping_text() {
+        // `,` and `}` are text at the top level, but not in a function
+        wraps_text(b"a,b}c", 0..5, b"{w,a\\,b\\}c}", b"a,b}c");
+        wraps_text(b"x\\d\\,\\n", 1..6, b"x{w,\\d\\,\\\\}n", b"\\d,\\");
+    }
+}
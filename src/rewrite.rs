@@ -0,0 +1,380 @@
+//! rewrites functions which match a pattern, like `{bold,{bold,$x}}` to
+//! `{bold,$x}`. patterns are written as expressions. an argument which is only
+//! `$` and a name of letters, digits and `_`, like `$x`, is a placeholder,
+//! which matches any argument. a placeholder used more than once must match
+//! the same tokens each time. in the replacement, an argument which is a
+//! placeholder, or the whole replacement, becomes the text which matched it,
+//! escapes and all
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::edit::{apply, Edit};
+use crate::incremental::close_offset;
+use crate::schema::literal;
+use crate::{function_args, tokenize_to_vec, Token};
+
+/// the most passes over the input before giving up, since rules like
+/// `{a,$x}` to `{a,{a,$x}}` never finish
+const MAX_PASSES: usize = 1000;
+
+/// the output can be this many times the length of the input, or this many
+/// KiB for short inputs, before giving up. rules like `{a,$x}` to
+/// `{a,$x$x}` would otherwise use up memory long before MAX_PASSES
+const MAX_GROWTH: usize = 64;
+
+const UNFINISHED: (usize, &str) = (0, "rewrites didn't finish");
+
+/// whether two ranges of tokens are the same, apart from offsets. deltas are
+/// relative, so this compares them in order rather than by nesting
+pub(crate) fn same_tokens(a: &[Token], b: &[Token]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (Token::Character(a), Token::Character(b)) => a.val == b.val,
+            (Token::Function(a), Token::Function(b)) => {
+                a.name == b.name
+                    && a.num_args == b.num_args
+                    && a.delta == b.delta
+                    && a.first_arg_delta == b.first_arg_delta
+            }
+            (Token::FunctionArgEnd(a), Token::FunctionArgEnd(b)) => a.arg_delta == b.arg_delta,
+            _ => false,
+        })
+}
+
+/// the bytes of the argument with the given tokens, of the function at
+/// tokens\[index\]. doesn't include the separators
+pub(crate) fn arg_span(tokens: &[Token], index: usize, arg: &Range<usize>) -> Range<usize> {
+    let begin = match &tokens[arg.start - 1] {
+        Token::Function(function) if arg.start - 1 == index => {
+            function.offset + 1 + function.name.len() + 1
+        }
+        token => token.offset() + 1,
+    };
+    begin..tokens[arg.end].offset()
+}
+
+/// the name of a placeholder, if these tokens are one
+fn placeholder(tokens: &[Token]) -> Option<Vec<u8>> {
+    literal(tokens).filter(|text| match text.split_first() {
+        Some((b'$', name)) => {
+            !name.is_empty()
+                && name
+                    .iter()
+                    .all(|ch| ch.is_ascii_alphanumeric() || *ch == b'_')
+        }
+        _ => false,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pattern: Vec<u8>,
+    replacement: Vec<u8>,
+    /// where each placeholder is in the replacement
+    holes: Vec<(Range<usize>, Vec<u8>)>,
+}
+
+impl Rule {
+    /// the pattern must be one function. on err, gives the offset and reason,
+    /// in the pattern or replacement
+    pub fn new(pattern: &[u8], replacement: &[u8]) -> Result<Self, (usize, &'static str)> {
+        let tokens = tokenize_to_vec(pattern)?;
+        match tokens.first() {
+            Some(Token::Function(function)) if function.delta == tokens.len() => (),
+            _ => return Err((0, "pattern must be one function")),
+        }
+        let mut bound = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if let Token::Function(_) = token {
+                bound.extend(function_args(&tokens, i).filter_map(|arg| placeholder(&tokens[arg])));
+            }
+        }
+
+        let replacement_tokens = tokenize_to_vec(replacement)?;
+        let mut holes = Vec::new();
+        match placeholder(&replacement_tokens) {
+            Some(name) => holes.push((0..replacement.len(), name)),
+            None => {
+                for (i, token) in replacement_tokens.iter().enumerate() {
+                    if let Token::Function(_) = token {
+                        for arg in function_args(&replacement_tokens, i) {
+                            if let Some(name) = placeholder(&replacement_tokens[arg.clone()]) {
+                                holes.push((arg_span(&replacement_tokens, i, &arg), name));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((range, _)) = holes.iter().find(|(_, name)| !bound.contains(name)) {
+            return Err((range.start, "unbound placeholder"));
+        }
+        Ok(Self {
+            pattern: pattern.to_vec(),
+            replacement: replacement.to_vec(),
+            holes,
+        })
+    }
+}
+
+/// the tokens and input bytes which matched a placeholder
+struct Binding {
+    name: Vec<u8>,
+    tokens: Range<usize>,
+    bytes: Range<usize>,
+}
+
+/// whether the functions at pattern\[p\] and tokens\[t\] match. this only
+/// recurses as deep as the pattern
+fn match_function(
+    pattern: &[Token],
+    p: usize,
+    tokens: &[Token],
+    t: usize,
+    bindings: &mut Vec<Binding>,
+) -> bool {
+    let same = matches!(
+        (&pattern[p], &tokens[t]),
+        (Token::Function(a), Token::Function(b)) if a.name == b.name && a.num_args == b.num_args
+    );
+    if !same {
+        return false;
+    }
+    for (p_arg, t_arg) in function_args(pattern, p).zip(function_args(tokens, t)) {
+        let name = match placeholder(&pattern[p_arg.clone()]) {
+            Some(v) => v,
+            None => {
+                if !match_sequence(pattern, p_arg, tokens, t_arg, bindings) {
+                    return false;
+                }
+                continue;
+            }
+        };
+        match bindings.iter().find(|binding| binding.name == name) {
+            Some(binding) => {
+                if !same_tokens(&tokens[binding.tokens.clone()], &tokens[t_arg]) {
+                    return false;
+                }
+            }
+            None => bindings.push(Binding {
+                name,
+                bytes: arg_span(tokens, t, &t_arg),
+                tokens: t_arg,
+            }),
+        }
+    }
+    true
+}
+
+/// whether the characters and functions of two ranges match
+fn match_sequence(
+    pattern: &[Token],
+    p_range: Range<usize>,
+    tokens: &[Token],
+    t_range: Range<usize>,
+    bindings: &mut Vec<Binding>,
+) -> bool {
+    let (mut p, mut t) = (p_range.start, t_range.start);
+    while p < p_range.end && t < t_range.end {
+        match (&pattern[p], &tokens[t]) {
+            (Token::Character(a), Token::Character(b)) if a.val == b.val => {
+                p += 1;
+                t += 1;
+            }
+            (Token::Function(a), Token::Function(b)) => {
+                if !match_function(pattern, p, tokens, t, bindings) {
+                    return false;
+                }
+                p += a.delta;
+                t += b.delta;
+            }
+            _ => return false,
+        }
+    }
+    p == p_range.end && t == t_range.end
+}
+
+/// rewrites the outermost matches, which don't overlap. gives None if nothing
+/// matched
+fn pass(
+    input: &[u8],
+    rules: &[(&Rule, Vec<Token>)],
+    max_len: usize,
+) -> Result<Option<Vec<u8>>, (usize, &'static str)> {
+    let tokens = tokenize_to_vec(input)?;
+    let mut edits = Vec::new();
+    let mut len = input.len();
+    let mut i = 0;
+    while i < tokens.len() {
+        let function = match &tokens[i] {
+            Token::Function(v) => v,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let mut bindings = Vec::new();
+        let rule = rules.iter().find(|(_, pattern)| {
+            bindings.clear();
+            match_function(pattern, 0, &tokens, i, &mut bindings)
+        });
+        let rule = match rule {
+            Some((rule, _)) => rule,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let holes: Vec<Edit> = rule
+            .holes
+            .iter()
+            .map(|(range, name)| {
+                let binding = bindings
+                    .iter()
+                    .find(|b| &b.name == name)
+                    .expect("checked by Rule::new");
+                Edit::replace(range.clone(), &input[binding.bytes.clone()])
+            })
+            .collect();
+        let replacement = apply(&rule.replacement, &holes)?;
+        let close = close_offset(&tokens, i, function);
+        len = len - (close + 1 - function.offset) + replacement.len();
+        if len > max_len {
+            return Err(UNFINISHED);
+        }
+        edits.push(Edit::replace(function.offset..close + 1, &replacement));
+        i += function.delta;
+    }
+    if edits.is_empty() {
+        return Ok(None);
+    }
+    apply(input, &edits).map(Some)
+}
+
+/// applies the rules until none match, trying them in order at each function.
+/// on err, gives the same as `tokenize` of the input, or (0, "rewrites didn't
+/// finish") if they went on too long or the output grew too large
+pub fn rewrite(input: &[u8], rules: &[Rule]) -> Result<Vec<u8>, (usize, &'static str)> {
+    let patterns: Vec<(&Rule, Vec<Token>)> = rules
+        .iter()
+        .map(|rule| {
+            (
+                rule,
+                tokenize_to_vec(&rule.pattern).expect("checked by Rule::new"),
+            )
+        })
+        .collect();
+    let max_len = input.len().max(1024).saturating_mul(MAX_GROWTH);
+    let mut output = input.to_vec();
+    for _ in 0..MAX_PASSES {
+        match pass(&output, &patterns, max_len)? {
+            Some(next) => output = next,
+            None => return Ok(output),
+        }
+    }
+    Err(UNFINISHED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[(&str, &str)]) -> Vec<Rule> {
+        rules
+            .iter()
+            .map(|(a, b)| Rule::new(a.as_bytes(), b.as_bytes()).unwrap())
+            .collect()
+    }
+
+    fn check(rule_list: &[(&str, &str)], input: &str, expected: &str) {
+        let output = rewrite(input.as_bytes(), &rules(rule_list)).unwrap();
+        assert_eq!(core::str::from_utf8(&output).unwrap(), expected);
+    }
+
+    #[test]
+    fn rewriting() {
+        let bold = [("{bold,{bold,$x}}", "{bold,$x}")];
+        check(&bold, "a{bold,{bold,b\\,c}}d", "a{bold,b\\,c}d");
+        check(&bold, "{bold,{bold,{bold,{bold,x}}}}", "{bold,x}");
+        check(
+            &bold,
+            "{bold,{bold,x},y}{bold,x}",
+            "{bold,{bold,x},y}{bold,x}",
+        );
+        check(
+            &bold,
+            "{link,{bold,{bold,{i,x}}},{bold,{bold,}}}",
+            "{link,{bold,{i,x}},{bold,}}",
+        );
+        // the old name of a function, with its args swapped
+        check(
+            &[("{old,$a,$b}", "{new,$b,$a}")],
+            "{old,1,{old,2,3}}",
+            "{new,{new,3,2},1}",
+        );
+        check(&[("{id,$x}", "$x")], "<{id,{id,ab}}>", "<ab>");
+        check(
+            &[("{if,yes,$x}", "$x")],
+            "{if,no,a}{if,yes,b}",
+            "{if,no,a}b",
+        );
+    }
+
+    #[test]
+    fn repeated_placeholders() {
+        let same = [("{eq,$x,$x}", "true")];
+        check(&same, "{eq,a{b},a{b}}{eq,a,b}", "true{eq,a,b}");
+        check(&same, "{eq,{f,\\,x},{f,\\,y}}", "{eq,{f,\\,x},{f,\\,y}}");
+
+        // bound args are compared without recursing through them
+        let mut nested = b"{f,".repeat(200_000);
+        nested.extend(b"}".repeat(200_000));
+        let mut input = b"{eq,".to_vec();
+        input.extend_from_slice(&nested);
+        input.push(b',');
+        input.extend_from_slice(&nested);
+        input.push(b'}');
+        assert_eq!(rewrite(&input, &rules(&same)), Ok(b"true".to_vec()));
+        // placeholders in text are only text when they aren't a whole argument
+        check(&[("{f,$x y}", "g")], "{f,$x y}{f,z}", "g{f,z}");
+    }
+
+    #[test]
+    fn rules_in_order() {
+        let list = [
+            ("{a,$x}", "{b,$x}"),
+            ("{b,$x}", "{c,$x}"),
+            ("{a,$x}", "never"),
+        ];
+        check(&list, "{a,1}", "{c,1}");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Rule::new(b"a{b}", b""),
+            Err((0, "pattern must be one function"))
+        );
+        assert_eq!(
+            Rule::new(b"{b}{c}", b""),
+            Err((0, "pattern must be one function"))
+        );
+        assert_eq!(
+            Rule::new(b"{f,$x}", b"{g,$y}"),
+            Err((3, "unbound placeholder"))
+        );
+        assert_eq!(Rule::new(b"{f,$x", b""), Err((0, "unclosed function")));
+        let grow = rules(&[("{a,$x}", "{a,{a,$x}}")]);
+        assert_eq!(rewrite(b"{a,1}", &grow), Err((0, "rewrites didn't finish")));
+        let double = rules(&[("{d,$x}", "{d,{c,$x}{c,$x}}")]);
+        assert_eq!(
+            rewrite(b"{d,ab}", &double),
+            Err((0, "rewrites didn't finish"))
+        );
+        assert_eq!(
+            rewrite(b"{a", &grow),
+            Err((1, "function name wasn't completed"))
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! checks function calls against a table of signatures, without evaluating
+//! anything. this catches unknown names and wrong arg counts before an
+//! expression is used
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::eval::Number;
+use crate::{function_args, Token};
+
+/// what an arg is allowed to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Any,
+    /// only characters, no function calls
+    Text,
+    /// characters which parse as a number. a function call could give a
+    /// number, so args with calls are allowed
+    Number,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature<'s> {
+    pub name: &'s [u8],
+    pub min_args: usize,
+    /// None if there's no limit
+    pub max_args: Option<usize>,
+    /// the kind of each arg. the last kind applies to any args past the end.
+    /// empty allows anything
+    pub arg_kinds: &'s [ArgKind],
+}
+
+impl<'s> Signature<'s> {
+    pub const fn new(name: &'s [u8], min_args: usize, max_args: Option<usize>) -> Self {
+        Self {
+            name,
+            min_args,
+            max_args,
+            arg_kinds: &[],
+        }
+    }
+
+    pub const fn with_arg_kinds(mut self, arg_kinds: &'s [ArgKind]) -> Self {
+        self.arg_kinds = arg_kinds;
+        self
+    }
+
+    /// the kind of arg n
+    pub fn arg_kind(&self, n: usize) -> ArgKind {
+        match self.arg_kinds.get(n).or(self.arg_kinds.last()) {
+            Some(v) => *v,
+            None => ArgKind::Any,
+        }
+    }
+}
+
+/// the known functions
+#[derive(Debug, Clone, Default)]
+pub struct Schema<'s> {
+    signatures: BTreeMap<&'s [u8], Signature<'s>>,
+}
+
+impl<'s> Schema<'s> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// replaces any signature with the same name
+    pub fn add(&mut self, signature: Signature<'s>) {
+        self.signatures.insert(signature.name, signature);
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<&Signature<'s>> {
+        self.signatures.get(name)
+    }
+
+    /// in order of name
+    pub fn signatures(&self) -> impl Iterator<Item = &Signature<'s>> {
+        self.signatures.values()
+    }
+}
+
+impl Schema<'static> {
+    /// the builtins in `Environment::standard`
+    pub fn standard() -> Self {
+        let mut schema = Self::new();
+        for signature in STANDARD {
+            schema.add(*signature);
+        }
+        schema
+    }
+}
+
+use ArgKind::{Any, Number as Num};
+
+const STANDARD: &[Signature<'static>] = &[
+    Signature::new(b"abs", 1, Some(1)).with_arg_kinds(&[Num]),
+    Signature::new(b"add", 1, None).with_arg_kinds(&[Num]),
+    Signature::new(b"and", 0, None),
+    Signature::new(b"arg", 1, Some(1)).with_arg_kinds(&[Num]),
+    Signature::new(b"concat", 0, None),
+    Signature::new(b"count", 1, Some(1)),
+    Signature::new(b"date", 1, Some(2)).with_arg_kinds(&[Num, Any]),
+    Signature::new(b"def", 2, Some(2)),
+    Signature::new(b"div", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"eq", 2, Some(2)),
+    Signature::new(b"filter", 2, Some(2)),
+    Signature::new(b"foreach", 3, Some(3)),
+    Signature::new(b"gt", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"if", 2, Some(3)),
+    Signature::new(b"join", 1, Some(2)),
+    Signature::new(b"len", 1, Some(1)),
+    Signature::new(b"let", 3, Some(3)),
+    Signature::new(b"list", 0, None),
+    Signature::new(b"lt", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"map", 2, Some(2)),
+    Signature::new(b"max", 1, None).with_arg_kinds(&[Num]),
+    Signature::new(b"min", 1, None).with_arg_kinds(&[Num]),
+    Signature::new(b"mod", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"mul", 1, None).with_arg_kinds(&[Num]),
+    Signature::new(b"not", 1, Some(1)),
+    Signature::new(b"nth", 2, Some(2)).with_arg_kinds(&[Any, Num]),
+    Signature::new(b"number", 1, Some(2)).with_arg_kinds(&[Num, Any]),
+    Signature::new(b"or", 0, None),
+    Signature::new(b"ordinal", 1, None).with_arg_kinds(&[Num, Any]),
+    Signature::new(b"plural", 1, None).with_arg_kinds(&[Num, Any]),
+    Signature::new(b"range", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"repeat", 2, Some(2)).with_arg_kinds(&[Any, Num]),
+    Signature::new(b"round", 1, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"select", 1, None),
+    Signature::new(b"split", 2, Some(2)),
+    Signature::new(b"sub", 2, Some(2)).with_arg_kinds(&[Num]),
+    Signature::new(b"switch", 1, None),
+    Signature::new(b"var", 1, Some(2)),
+];
+
+/// a problem found by `check`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    /// where in the input
+    pub offset: usize,
+    /// the index of the FUNCTION token
+    pub index: usize,
+    pub reason: &'static str,
+}
+
+/// the literal text of some tokens, or None if there's a function call
+pub(crate) fn literal(tokens: &[Token]) -> Option<Vec<u8>> {
+    tokens
+        .iter()
+        .map(|t| match t {
+            Token::Character(c) => Some(c.val),
+            _ => None,
+        })
+        .collect()
+}
+
+/// checks every function in tokens from a successful `tokenize`. `{$name}`
+/// variables can't have args. if the schema has `def`, then functions defined
+/// with a literal name are known too, and take any number of args
+pub fn check(tokens: &[Token], schema: &Schema) -> Vec<Violation> {
+    let mut defined = Vec::new();
+    if schema.get(b"def").is_some() {
+        for (i, token) in tokens.iter().enumerate() {
+            if let Token::Function(function) = token {
+                if function.name == b"def" {
+                    let name = function_args(tokens, i).next().map(|r| literal(&tokens[r]));
+                    if let Some(Some(name)) = name {
+                        defined.push(name);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut violations = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let function = match token {
+            Token::Function(v) => v,
+            _ => continue,
+        };
+        let mut violation = |offset, reason| {
+            violations.push(Violation {
+                offset,
+                index: i,
+                reason,
+            })
+        };
+        if function.name.first() == Some(&b'$') {
+            if function.num_args != 0 {
+                violation(function.offset, "variables don't take arguments");
+            }
+            continue;
+        }
+        let signature = match schema.get(function.name) {
+            Some(v) => v,
+            None => {
+                if !defined.iter().any(|d| d == function.name) {
+                    violation(function.offset, "unknown function");
+                }
+                continue;
+            }
+        };
+        if function.num_args < signature.min_args {
+            violation(function.offset, "too few arguments");
+        }
+        if signature
+            .max_args
+            .is_some_and(|max| function.num_args > max)
+        {
+            violation(function.offset, "too many arguments");
+        }
+        for (n, range) in function_args(tokens, i).enumerate() {
+            let offset = tokens[range.start].offset();
+            let text = literal(&tokens[range]);
+            match (signature.arg_kind(n), text) {
+                (ArgKind::Text, None) => violation(offset, "expected text without functions"),
+                (ArgKind::Number, Some(text)) if Number::parse(&text).is_none() => {
+                    violation(offset, "expected a number")
+                }
+                _ => (),
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Environment;
+    use crate::tokenize_to_vec;
+
+    fn check_standard(input: &[u8]) -> Vec<(usize, &'static str)> {
+        let tokens = tokenize_to_vec(input).unwrap();
+        let violations = check(&tokens, &Schema::standard());
+        violations.iter().map(|v| (v.offset, v.reason)).collect()
+    }
+
+    #[test]
+    fn standard_matches_environment() {
+        let schema = Schema::standard();
+        let env = Environment::standard();
+        let names: Vec<_> = schema.signatures().map(|s| s.name).collect();
+        assert_eq!(names, env.names().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn arity() {
+        assert_eq!(
+            check_standard(b"{if,a,b} {if,a} {not,a,b}"),
+            [(9, "too few arguments"), (16, "too many arguments")]
+        );
+        assert_eq!(
+            check_standard(b"{nope}{if,{nope,1},b}"),
+            [(0, "unknown function"), (10, "unknown function")]
+        );
+        assert_eq!(
+            check_standard(b"{$a}{$a,b}"),
+            [(4, "variables don't take arguments")]
+        );
+    }
+
+    #[test]
+    fn defined() {
+        assert_eq!(check_standard(b"{greet,Bob}{def,greet,hi {arg,0}}"), []);
+        assert_eq!(
+            check_standard(b"{def,{$name},x}{greet}"),
+            [(15, "unknown function")]
+        );
+    }
+
+    #[test]
+    fn arg_kinds() {
+        assert_eq!(check_standard(b"{add,1,{$x},2.5}"), []);
+        assert_eq!(
+            check_standard(b"{add,1,x}{round,1,}"),
+            [(7, "expected a number"), (18, "expected a number")]
+        );
+
+        let mut schema = Schema::new();
+        schema.add(
+            Signature::new(b"link", 2, Some(2)).with_arg_kinds(&[ArgKind::Text, ArgKind::Any]),
+        );
+        let tokens = tokenize_to_vec(b"{link,{x},{y}}").unwrap();
+        let violations = check(&tokens, &schema);
+        assert_eq!(
+            violations,
+            [
+                Violation {
+                    offset: 6,
+                    index: 0,
+                    reason: "expected text without functions"
+                },
+                Violation {
+                    offset: 6,
+                    index: 1,
+                    reason: "unknown function"
+                },
+                Violation {
+                    offset: 10,
+                    index: 3,
+                    reason: "unknown function"
+                },
+            ]
+        );
+    }
+}
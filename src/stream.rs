@@ -0,0 +1,273 @@
+//! tokenizes input which arrives in chunks. tokens are given as soon as they
+//! are known, so unlike `tokenize`, a function's number of args and deltas
+//! can't be given up front. instead, the end of each function is its own token
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::Character;
+
+/// how function names are given, since a name can be split across chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameMode {
+    /// the range of the name in the whole input. nothing is allocated
+    Span,
+    /// a copy of the name
+    Owned,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Name {
+    Span(Range<usize>),
+    Owned(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamToken {
+    Character(Character),
+    /// given once the name is complete
+    Function {
+        offset: usize,
+        name: Name,
+    },
+    /// a `,` or `}` which ends an argument, like END_ARG
+    FunctionArgEnd {
+        offset: usize,
+    },
+    /// the `}` of a function. comes after the END_ARG of its last argument
+    FunctionEnd {
+        offset: usize,
+        num_args: usize,
+    },
+}
+
+/// a push based tokenizer. offsets are from the start of the first chunk
+#[derive(Debug, Clone)]
+pub struct StreamTokenizer {
+    mode: NameMode,
+    /// the offset of the next chunk
+    offset: usize,
+    escaped: bool,
+    /// the offset of the `{` of a name being scanned
+    name_begin: Option<usize>,
+    /// the name so far, in `NameMode::Owned`
+    name: Vec<u8>,
+    /// the offset and number of args so far of each open function
+    functions: Vec<(usize, usize)>,
+}
+
+impl StreamTokenizer {
+    pub fn new(mode: NameMode) -> Self {
+        Self {
+            mode,
+            offset: 0,
+            escaped: false,
+            name_begin: None,
+            name: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// tokenizes the next chunk of input, giving each token to emit
+    pub fn push(&mut self, chunk: &[u8], emit: &mut impl FnMut(StreamToken)) {
+        for (i, ch) in chunk.iter().enumerate() {
+            let i = self.offset + i;
+            if let Some(begin) = self.name_begin {
+                if *ch != b',' && *ch != b'}' {
+                    if self.mode == NameMode::Owned {
+                        self.name.push(*ch);
+                    }
+                    continue;
+                }
+                self.name_begin = None;
+                let name = match self.mode {
+                    NameMode::Span => Name::Span(begin + 1..i),
+                    NameMode::Owned => Name::Owned(core::mem::take(&mut self.name)),
+                };
+                emit(StreamToken::Function {
+                    offset: begin,
+                    name,
+                });
+                if *ch == b',' {
+                    self.functions.push((begin, 0));
+                } else {
+                    emit(StreamToken::FunctionEnd {
+                        offset: i,
+                        num_args: 0,
+                    });
+                }
+                continue;
+            }
+
+            if self.escaped {
+                self.escaped = false;
+                if !matches!(*ch, b'{' | b'}' | b',' | b'\\') {
+                    // not escapable, so both are sent through
+                    emit(StreamToken::Character(Character {
+                        offset: i - 1,
+                        val: b'\\',
+                    }));
+                    emit(StreamToken::Character(Character {
+                        offset: i,
+                        val: *ch,
+                    }));
+                } else {
+                    emit(StreamToken::Character(Character {
+                        offset: i - 1,
+                        val: *ch,
+                    }));
+                }
+                continue;
+            }
+
+            match *ch {
+                b'\\' => self.escaped = true,
+                b'{' => self.name_begin = Some(i),
+                b',' | b'}' if !self.functions.is_empty() => {
+                    emit(StreamToken::FunctionArgEnd { offset: i });
+                    let function = self.functions.last_mut().expect("checked above");
+                    function.1 += 1;
+                    if *ch == b'}' {
+                        let (_, num_args) = self.functions.pop().expect("checked above");
+                        emit(StreamToken::FunctionEnd {
+                            offset: i,
+                            num_args,
+                        });
+                    }
+                }
+                _ => emit(StreamToken::Character(Character {
+                    offset: i,
+                    val: *ch,
+                })),
+            }
+        }
+        self.offset += chunk.len();
+    }
+
+    /// ends the input. on err, gives the same as `tokenize`. like `tokenize`,
+    /// a trailing escape is ignored
+    pub fn finish(self) -> Result<(), (usize, &'static str)> {
+        if let Some(begin) = self.name_begin {
+            return Err((begin + 1, "function name wasn't completed"));
+        }
+        if let Some((offset, _)) = self.functions.last() {
+            return Err((*offset, "unclosed function"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tokenize, tokenize_to_vec, Token};
+
+    /// the stream tokens which match the tokens from `tokenize`
+    fn expected(tokens: &[Token]) -> Vec<StreamToken> {
+        let mut out = Vec::new();
+        // the number of args of each open function
+        let mut num_args = Vec::new();
+        for token in tokens {
+            match token {
+                Token::Character(c) => out.push(StreamToken::Character(*c)),
+                Token::Function(f) => {
+                    let name_end = f.offset + 1 + f.name.len();
+                    out.push(StreamToken::Function {
+                        offset: f.offset,
+                        name: Name::Span(f.offset + 1..name_end),
+                    });
+                    match f.first_arg_delta {
+                        Some(_) => num_args.push(f.num_args),
+                        None => out.push(StreamToken::FunctionEnd {
+                            offset: name_end,
+                            num_args: 0,
+                        }),
+                    }
+                }
+                Token::FunctionArgEnd(arg_end) => {
+                    out.push(StreamToken::FunctionArgEnd {
+                        offset: arg_end.offset,
+                    });
+                    if arg_end.arg_delta.is_none() {
+                        out.push(StreamToken::FunctionEnd {
+                            offset: arg_end.offset,
+                            num_args: num_args.pop().unwrap(),
+                        });
+                    }
+                }
+                Token::Invalid => (),
+            }
+        }
+        out
+    }
+
+    fn stream(
+        chunks: &[&[u8]],
+        mode: NameMode,
+    ) -> (Vec<StreamToken>, Result<(), (usize, &'static str)>) {
+        let mut tokenizer = StreamTokenizer::new(mode);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            tokenizer.push(chunk, &mut |token| out.push(token));
+        }
+        (out, tokenizer.finish())
+    }
+
+    #[test]
+    fn every_split() {
+        let inputs: [&[u8]; 5] = [
+            b"{outer,{inner,ab,c},1,2}z",
+            b",\\{{a,\\,}\\a\\n\\{\\\\",
+            b"{}{,}{name}a}b,",
+            b"{long_name,x\\}}\\",
+            b"",
+        ];
+        for input in inputs {
+            let want = expected(&tokenize_to_vec(input).unwrap());
+            for split in 0..=input.len() {
+                let (got, result) = stream(&[&input[..split], &input[split..]], NameMode::Span);
+                assert_eq!(
+                    got,
+                    want,
+                    "{:?} split at {}",
+                    core::str::from_utf8(input),
+                    split
+                );
+                assert_eq!(result, Ok(()));
+            }
+            let bytes: Vec<&[u8]> = input.chunks(1).collect();
+            assert_eq!(stream(&bytes, NameMode::Span).0, want);
+        }
+    }
+
+    #[test]
+    fn owned_names() {
+        let (got, _) = stream(&[b"x{lo", b"ng", b"_na", b"me,a}{b}"], NameMode::Owned);
+        assert_eq!(
+            got[1],
+            StreamToken::Function {
+                offset: 1,
+                name: Name::Owned(b"long_name".to_vec())
+            }
+        );
+        assert_eq!(
+            got[5],
+            StreamToken::Function {
+                offset: 14,
+                name: Name::Owned(b"b".to_vec())
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for input in [&b"{hi"[..], b"{hi,ab", b"{a,{b,c}", b"x{a,\\}"] {
+            let mut stack = alloc::vec![0; input.len()];
+            let size = tokenize(input, &mut stack, &mut None);
+            let mut output = alloc::vec![Token::default(); size.unwrap_or(0)];
+            let want = size.and_then(|_| tokenize(input, &mut stack, &mut Some(&mut output)));
+            let (_, got) = stream(&[input], NameMode::Span);
+            assert_eq!(got, want.map(|_| ()), "{:?}", core::str::from_utf8(input));
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! walks the tree of tokens, so analyses don't each follow the deltas by hand
+
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::Range;
+
+use crate::{function_args, Character, Function, FunctionArgs, Token};
+
+/// how deeply functions can be nested for `walk`. visitors recurse for each
+/// function, so this bounds the stack used
+pub const MAX_DEPTH: usize = 256;
+
+/// each method has a default which goes into the children. an override can
+/// call `walk_function` or `walk_range` to keep going, or not to skip them
+#[allow(unused_variables)]
+pub trait Visitor<'a> {
+    /// a character at tokens\[index\]
+    fn visit_character(&mut self, index: usize, character: &Character) {}
+
+    /// a function at tokens\[index\]
+    fn visit_function(&mut self, tokens: &[Token<'a>], index: usize, function: &Function<'a>) {
+        walk_function(self, tokens, index);
+    }
+
+    /// argument n of the function at tokens\[function\]. range is its tokens,
+    /// not including its END_ARG
+    fn visit_arg(&mut self, tokens: &[Token<'a>], function: usize, n: usize, range: Range<usize>) {
+        walk_range(self, tokens, range);
+    }
+}
+
+/// the offset of the first function nested more than MAX_DEPTH deep
+fn too_deep(tokens: &[Token]) -> Option<usize> {
+    // one past the end of each function the current token is in
+    let mut ends: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        while ends.last().is_some_and(|end| *end <= i) {
+            ends.pop();
+        }
+        if let Token::Function(function) = token {
+            if ends.len() == MAX_DEPTH {
+                return Some(function.offset);
+            }
+            ends.push(i + function.delta);
+        }
+    }
+    None
+}
+
+/// visits all the tokens. on err, gives the offset of a function nested more
+/// than MAX_DEPTH deep, and nothing is visited
+pub fn walk<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    tokens: &[Token<'a>],
+) -> Result<(), (usize, &'static str)> {
+    if let Some(offset) = too_deep(tokens) {
+        return Err((offset, "nested too deeply"));
+    }
+    walk_range(visitor, tokens, 0..tokens.len());
+    Ok(())
+}
+
+/// visits the characters and functions in a range, which must not split a
+/// function, like an argument's range. unlike `walk`, the nesting isn't
+/// checked, so this is for going on from a visit
+pub fn walk_range<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    tokens: &[Token<'a>],
+    range: Range<usize>,
+) {
+    let mut i = range.start;
+    while i < range.end {
+        match &tokens[i] {
+            Token::Character(character) => {
+                visitor.visit_character(i, character);
+                i += 1;
+            }
+            Token::Function(function) => {
+                visitor.visit_function(tokens, i, function);
+                i += function.delta;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// visits each argument of the function at tokens\[index\]
+pub fn walk_function<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    tokens: &[Token<'a>],
+    index: usize,
+) {
+    for (n, range) in function_args(tokens, index).enumerate() {
+        visitor.visit_arg(tokens, index, n, range);
+    }
+}
+
+/// makes a value for each part of the tree, from the values of its children
+pub trait Fold<'a> {
+    type Output;
+
+    /// a character at tokens\[index\]
+    fn fold_character(&mut self, index: usize, character: &Character) -> Self::Output;
+
+    /// a function at tokens\[index\], given the value of each argument
+    fn fold_function(
+        &mut self,
+        index: usize,
+        function: &Function<'a>,
+        args: Vec<Self::Output>,
+    ) -> Self::Output;
+
+    /// the characters and functions of an argument, or of the whole input
+    fn fold_sequence(&mut self, items: Vec<Self::Output>) -> Self::Output;
+}
+
+/// folds all the tokens
+pub fn fold<'a, F: Fold<'a> + ?Sized>(folder: &mut F, tokens: &[Token<'a>]) -> F::Output {
+    fold_range(folder, tokens, 0..tokens.len())
+}
+
+/// a function whose arguments are being folded
+struct Open<'t, 'a, O> {
+    index: usize,
+    function: &'t Function<'a>,
+    /// the arguments after the one being folded
+    rest: FunctionArgs<'t, 'a>,
+    args: Vec<O>,
+    /// the values before it in the sequence it's in, and where that goes on
+    items: Vec<O>,
+    next: Range<usize>,
+}
+
+/// folds the characters and functions in a range, which must not split a
+/// function. this keeps its own stack, so any nesting can be folded
+pub fn fold_range<'t, 'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    tokens: &'t [Token<'a>],
+    range: Range<usize>,
+) -> F::Output {
+    let mut open: Vec<Open<'t, 'a, F::Output>> = Vec::new();
+    let mut items = Vec::new();
+    let mut range = range;
+    loop {
+        if range.start < range.end {
+            let i = range.start;
+            match &tokens[i] {
+                Token::Character(character) => {
+                    items.push(folder.fold_character(i, character));
+                    range.start += 1;
+                }
+                Token::Function(function) => {
+                    let mut rest = function_args(tokens, i);
+                    let next = i + function.delta..range.end;
+                    match rest.next() {
+                        Some(arg) => {
+                            open.push(Open {
+                                index: i,
+                                function,
+                                rest,
+                                args: Vec::new(),
+                                items: mem::take(&mut items),
+                                next,
+                            });
+                            range = arg;
+                        }
+                        None => {
+                            items.push(folder.fold_function(i, function, Vec::new()));
+                            range = next;
+                        }
+                    }
+                }
+                _ => range.start += 1,
+            }
+            continue;
+        }
+
+        // the end of an argument, or of everything
+        let value = folder.fold_sequence(mem::take(&mut items));
+        let function = match open.last_mut() {
+            Some(v) => v,
+            None => return value,
+        };
+        function.args.push(value);
+        if let Some(arg) = function.rest.next() {
+            range = arg;
+            continue;
+        }
+        let function = open.pop().expect("checked above");
+        items = function.items;
+        items.push(folder.fold_function(function.index, function.function, function.args));
+        range = function.next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize_to_vec;
+    use alloc::vec;
+
+    /// the names of functions, and the deepest nesting
+    #[derive(Default)]
+    struct Names<'a> {
+        names: Vec<&'a [u8]>,
+        depth: usize,
+        max_depth: usize,
+        characters: usize,
+    }
+
+    impl<'a> Visitor<'a> for Names<'a> {
+        fn visit_character(&mut self, _index: usize, _character: &Character) {
+            self.characters += 1;
+        }
+
+        fn visit_function(&mut self, tokens: &[Token<'a>], index: usize, function: &Function<'a>) {
+            self.names.push(function.name);
+            // the args of skip aren't visited
+            if function.name != b"skip" {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+                walk_function(self, tokens, index);
+                self.depth -= 1;
+            }
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        let tokens = tokenize_to_vec(b"ab{f,{g,x},{h}}{skip,{i},yz}{j}c").unwrap();
+        let mut names = Names::default();
+        walk(&mut names, &tokens).unwrap();
+        assert_eq!(names.names, [&b"f"[..], b"g", b"h", b"skip", b"j"]);
+        assert_eq!(names.max_depth, 2);
+        assert_eq!(names.characters, 4);
+    }
+
+    /// the arg numbers, with the default visit_function
+    #[test]
+    fn default_methods() {
+        struct Args(Vec<(usize, usize, Range<usize>)>);
+        impl<'a> Visitor<'a> for Args {
+            fn visit_arg(
+                &mut self,
+                tokens: &[Token<'a>],
+                function: usize,
+                n: usize,
+                range: Range<usize>,
+            ) {
+                self.0.push((function, n, range.clone()));
+                walk_range(self, tokens, range);
+            }
+        }
+        let tokens = tokenize_to_vec(b"{f,a,{g,b}}").unwrap();
+        let mut args = Args(Vec::new());
+        walk(&mut args, &tokens).unwrap();
+        assert_eq!(args.0, [(0, 0, 1..2), (0, 1, 3..6), (3, 0, 4..5)]);
+    }
+
+    /// writes the tokens back out as input
+    struct Source;
+
+    impl<'a> Fold<'a> for Source {
+        type Output = Vec<u8>;
+
+        fn fold_character(&mut self, _index: usize, character: &Character) -> Vec<u8> {
+            match character.val {
+                b'{' | b'}' | b',' | b'\\' => vec![b'\\', character.val],
+                val => vec![val],
+            }
+        }
+
+        fn fold_function(
+            &mut self,
+            _index: usize,
+            function: &Function<'a>,
+            args: Vec<Vec<u8>>,
+        ) -> Vec<u8> {
+            let mut out = vec![b'{'];
+            out.extend_from_slice(function.name);
+            for arg in args {
+                out.push(b',');
+                out.extend(arg);
+            }
+            out.push(b'}');
+            out
+        }
+
+        fn fold_sequence(&mut self, items: Vec<Vec<u8>>) -> Vec<u8> {
+            items.concat()
+        }
+    }
+
+    #[test]
+    fn fold_source() {
+        let input = b"a\\,{f,{g,x\\}},,{h}}b";
+        let tokens = tokenize_to_vec(input).unwrap();
+        assert_eq!(fold(&mut Source, &tokens), input);
+        assert_eq!(fold_range(&mut Source, &tokens, 3..7), b"{g,x\\}}");
+        assert_eq!(fold(&mut Source, &[]), b"");
+    }
+
+    /// `{f,` n times, then `}` n times
+    fn nested(n: usize) -> Vec<u8> {
+        let mut input = b"{f,".repeat(n);
+        input.extend(b"}".repeat(n));
+        input
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let input = nested(MAX_DEPTH);
+        let tokens = tokenize_to_vec(&input).unwrap();
+        let mut names = Names::default();
+        walk(&mut names, &tokens).unwrap();
+        assert_eq!(names.max_depth, MAX_DEPTH);
+
+        let input = nested(MAX_DEPTH + 1);
+        let tokens = tokenize_to_vec(&input).unwrap();
+        let mut names = Names::default();
+        let offset = MAX_DEPTH * 3;
+        assert_eq!(
+            walk(&mut names, &tokens),
+            Err((offset, "nested too deeply"))
+        );
+        assert!(names.names.is_empty());
+
+        // folding keeps its own stack
+        struct Depth;
+        impl<'a> Fold<'a> for Depth {
+            type Output = usize;
+            fn fold_character(&mut self, _index: usize, _character: &Character) -> usize {
+                0
+            }
+            fn fold_function(&mut self, _: usize, _: &Function<'a>, args: Vec<usize>) -> usize {
+                args.into_iter().max().unwrap_or(0) + 1
+            }
+            fn fold_sequence(&mut self, items: Vec<usize>) -> usize {
+                items.into_iter().max().unwrap_or(0)
+            }
+        }
+        let input = nested(100_000);
+        let tokens = tokenize_to_vec(&input).unwrap();
+        assert_eq!(fold(&mut Depth, &tokens), 100_000);
+    }
+}
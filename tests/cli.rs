@@ -0,0 +1,42 @@
+//! runs the lexpr binary
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn lexpr(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lexpr"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn fmt_stdin() {
+    let out = lexpr(&["fmt", "-"], b"a\\}{if,\\q,}");
+    assert!(out.status.success());
+    assert_eq!(out.stdout, b"a}{if,\\\\q,}");
+
+    // written even when it doesn't change
+    let out = lexpr(&["fmt", "-"], b"abc");
+    assert!(out.status.success());
+    assert_eq!(out.stdout, b"abc");
+
+    let out = lexpr(&["fmt", "--check", "-"], b"abc");
+    assert!(out.status.success());
+    assert_eq!(out.stdout, b"");
+}
+
+#[test]
+fn tokens_stdin() {
+    let out = lexpr(&["tokens", "-"], b"{f,a,}");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("END_ARG (delta: 1)\n"), "{}", stdout);
+    assert!(stdout.ends_with("END_ARG (delta: None)\n"), "{}", stdout);
+}